@@ -0,0 +1,55 @@
+//! Compares the cost of writing a frame to shared memory for the kitty
+//! graphics protocol, with a new object for every frame against reusing a
+//! ring of files.
+//!
+//! Run with `cargo bench --bench shared_memory`.
+
+// Included rather than exported, as the module is private to the crate. Only
+// some of it is used, and its tests aren't built here.
+#[allow(dead_code, unused_imports)]
+#[path = "../src/shared_memory.rs"]
+mod shared_memory;
+
+use shared_memory::{SharedMemoryBuffer, SharedMemoryRing};
+use std::time::{Duration, Instant};
+
+const FRAMES: u32 = 300;
+
+fn main() {
+    for (width, height) in [(800, 600), (1920, 1080), (3840, 2160)] {
+        let frame = vec![0x7f; width * height * 4];
+
+        let per_frame = bench(|i| {
+            let start = Instant::now();
+            let mut shm = SharedMemoryBuffer::new(frame.len(), i as u64).unwrap();
+            if let Some(map) = &mut shm.map {
+                map.copy_from_slice(&frame);
+                let _ = map.flush();
+            }
+            // Unlinks the object, like when the next frame replaces it.
+            drop(shm);
+            start.elapsed()
+        });
+
+        let mut ring = SharedMemoryRing::new(frame.len(), true).expect("the ring needs /dev/shm");
+        let reused = bench(|_| {
+            let start = Instant::now();
+            let path = ring.write(&frame).unwrap().to_path_buf();
+            let elapsed = start.elapsed();
+            // Read the frame like the terminal would, which completes the
+            // handshake so that the slot can be reused.
+            std::hint::black_box(std::fs::read(path).unwrap());
+            elapsed
+        });
+
+        println!(
+            "{}x{}: new object per frame {:?}, ring {:?}",
+            width, height, per_frame, reused
+        );
+    }
+}
+
+/// The average of the durations returned by `f` over [FRAMES] runs.
+fn bench(mut f: impl FnMut(u32) -> Duration) -> Duration {
+    (0..FRAMES).map(&mut f).sum::<Duration>() / FRAMES
+}
@@ -0,0 +1,341 @@
+//! Draws frames with block or braille characters and ANSI colors, where each
+//! cell is a small block of pixels drawn with two colors.
+
+use crate::color::{composite, dither, ColorDepth, Dithering, Rgb, TerminalColor};
+use crate::renderer::{Frame, Renderer};
+use crossterm::cursor::MoveTo;
+use crossterm::style::Print;
+use crossterm::QueueableCommand;
+use std::io::Write;
+use std::iter::zip;
+
+/// The characters which cells are drawn with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum AnsiGlyphs {
+    /// Half blocks, with 1x2 pixels per cell.
+    #[default]
+    HalfBlock,
+    /// Quadrant blocks, with 2x2 pixels per cell.
+    Quadrant,
+    /// Sextant blocks from Unicode 13, with 2x3 pixels per cell.
+    Sextant,
+    /// Braille patterns, with 2x4 pixels per cell.
+    Braille,
+}
+
+impl AnsiGlyphs {
+    /// The number of pixels in each cell, as columns and rows.
+    pub(crate) fn cell_pixels(self) -> (usize, usize) {
+        match self {
+            AnsiGlyphs::HalfBlock => (1, 2),
+            AnsiGlyphs::Quadrant => (2, 2),
+            AnsiGlyphs::Sextant => (2, 3),
+            AnsiGlyphs::Braille => (2, 4),
+        }
+    }
+
+    /// The character which draws the pixels set in `mask` in the foreground,
+    /// where bit `i` is the `i`th pixel of the cell in row-major order.
+    fn glyph(self, mask: u8) -> char {
+        match self {
+            AnsiGlyphs::HalfBlock => [' ', BLOCK_UPPER, BLOCK_LOWER, BLOCK_FULL][mask as usize],
+            AnsiGlyphs::Quadrant => QUADRANTS[mask as usize],
+            AnsiGlyphs::Sextant => match mask {
+                0 => ' ',
+                // The sextant block skips the characters which already exist as
+                // half and full blocks.
+                0b010101 => '▌',
+                0b101010 => '▐',
+                0b111111 => BLOCK_FULL,
+                _ => {
+                    let skipped = (mask > 0b010101) as u32 + (mask > 0b101010) as u32;
+                    char::from_u32(0x1FB00 + mask as u32 - 1 - skipped).unwrap()
+                }
+            },
+            AnsiGlyphs::Braille => {
+                // Braille dots are numbered down the left column, then down the
+                // right column, with the bottom row added last.
+                const DOTS: [u8; 8] = [0x01, 0x08, 0x02, 0x10, 0x04, 0x20, 0x40, 0x80];
+                let dots = (0..8)
+                    .filter(|i| mask & (1 << i) != 0)
+                    .fold(0, |dots, i| dots | DOTS[i]);
+                char::from_u32(0x2800 + dots as u32).unwrap()
+            }
+        }
+    }
+}
+
+const BLOCK_UPPER: char = '▀';
+const BLOCK_LOWER: char = '▄';
+const BLOCK_FULL: char = '█';
+
+/// Quadrant blocks, indexed by the top left, top right, bottom left and
+/// bottom right quadrants as the lowest to highest bits.
+const QUADRANTS: [char; 16] = [
+    ' ', '▘', '▝', '▀', '▖', '▌', '▞', '▛', '▗', '▚', '▐', '▜', '▄', '▙', '▟', '█',
+];
+
+pub(crate) struct AnsiRenderer {
+    glyphs: AnsiGlyphs,
+    color_depth: ColorDepth,
+    dithering: Dithering,
+    /// The cells which were drawn in the previous frame.
+    lines: Vec<Vec<TerminalCell>>,
+    /// The size of the area for the app, in cells.
+    columns: usize,
+    rows: usize,
+}
+
+impl AnsiRenderer {
+    pub(crate) fn new(glyphs: AnsiGlyphs, color_depth: ColorDepth, dithering: Dithering) -> Self {
+        Self {
+            glyphs,
+            color_depth,
+            dithering,
+            lines: vec![],
+            columns: 0,
+            rows: 0,
+        }
+    }
+}
+
+impl Renderer for AnsiRenderer {
+    fn draws_pixels(&self) -> bool {
+        false
+    }
+
+    fn present(&mut self, out: &mut dyn Write, frame: &Frame) -> std::io::Result<()> {
+        let (x_offset, y_offset) = frame.offset;
+        let (cell_cols, cell_rows) = (self.columns, self.rows);
+        let (pixels_per_col, pixels_per_row) = self.glyphs.cell_pixels();
+
+        // The pixels which are drawn, which is the area for the app.
+        let (width, height) = (cell_cols * pixels_per_col, cell_rows * pixels_per_row);
+        let mut image = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                let pixel = get_pixel(
+                    frame.buffer,
+                    frame.width,
+                    frame.height,
+                    x_offset + x as isize,
+                    y_offset + y as isize,
+                );
+                // Pixels past the edges of the frame, e.g. when it is panned,
+                // are drawn as the background of the terminal.
+                image.push(
+                    pixel.map_or(frame.background, |pixel| composite(pixel, frame.background)),
+                );
+            }
+        }
+        dither(&mut image, width, self.color_depth, self.dithering);
+
+        let mut lines = Vec::with_capacity(cell_rows);
+        let mut pixels = Vec::with_capacity(pixels_per_col * pixels_per_row);
+
+        for y in 0..cell_rows {
+            let mut row_cells = Vec::with_capacity(cell_cols);
+
+            for x in 0..cell_cols {
+                pixels.clear();
+                for dy in 0..pixels_per_row {
+                    let start = (y * pixels_per_row + dy) * width + x * pixels_per_col;
+                    pixels.extend_from_slice(&image[start..start + pixels_per_col]);
+                }
+
+                let (mask, foreground, background) = fit_two_colors(&pixels);
+
+                row_cells.push(TerminalCell {
+                    glyph: self.glyphs.glyph(mask),
+                    foreground: self.color_depth.to_color(foreground),
+                    background: self.color_depth.to_color(background),
+                    semantics: None,
+                });
+            }
+            lines.push(row_cells);
+        }
+
+        if self.lines.len() != lines.len() {
+            self.lines = vec![vec![]; lines.len()];
+        }
+
+        for (y, (prev, current)) in zip(&self.lines, &lines).enumerate() {
+            for (
+                x,
+                current_cell @ TerminalCell {
+                    glyph,
+                    foreground,
+                    background,
+                    semantics: _,
+                },
+            ) in current.iter().enumerate()
+            {
+                if prev
+                    .get(x)
+                    .filter(|prev_cell| prev_cell == &current_cell)
+                    .is_some()
+                {
+                    continue;
+                }
+                out.queue(MoveTo(x as u16, y as u16))?;
+                // Written without crossterm's styling, which always uses
+                // `38;5;{n}` for the 16 ANSI colors.
+                out.queue(Print(format!(
+                    "\x1b[{};{}m{}\x1b[0m",
+                    foreground.sgr_parameters(false),
+                    background.sgr_parameters(true),
+                    glyph
+                )))?;
+            }
+        }
+
+        self.lines = lines;
+        Ok(())
+    }
+
+    fn resize(&mut self, columns: usize, rows: usize) {
+        self.columns = columns;
+        self.rows = rows;
+        self.clear();
+    }
+
+    fn clear(&mut self) {
+        self.lines.clear();
+    }
+}
+
+#[derive(PartialEq, Eq, Clone)]
+struct TerminalCell {
+    glyph: char,
+    foreground: TerminalColor,
+    background: TerminalColor,
+    semantics: Option<String>,
+}
+
+/// Splits `pixels` into the two colors which best approximate them, with a
+/// few iterations of k-means.
+///
+/// Returns a mask of the pixels which are closer to the foreground, and the
+/// foreground and background colors.
+fn fit_two_colors(pixels: &[Rgb]) -> (u8, Rgb, Rgb) {
+    let luma = |(r, g, b): Rgb| 299 * r as u32 + 587 * g as u32 + 114 * b as u32;
+    let distance = |(r1, g1, b1): Rgb, (r2, g2, b2): Rgb| {
+        let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+        d(r1, r2) + d(g1, g2) + d(b1, b2)
+    };
+
+    // Start from the brightest and darkest pixels, so that the foreground is
+    // the brighter color. Pixels which are as bright are ordered by their
+    // color, so that these are only the same when every pixel is.
+    let key = |pixel: &&Rgb| (luma(**pixel), **pixel);
+    let mut foreground = *pixels.iter().max_by_key(key).unwrap();
+    let mut background = *pixels.iter().min_by_key(key).unwrap();
+    if foreground == background {
+        return (0, foreground, background);
+    }
+
+    let mut mask = 0;
+    for _ in 0..2 {
+        mask = pixels
+            .iter()
+            .enumerate()
+            .filter(|(_, pixel)| distance(**pixel, foreground) < distance(**pixel, background))
+            .fold(0u8, |mask, (i, _)| mask | (1 << i));
+
+        let mean = |selected: bool| {
+            let (sum, count) = pixels
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| (mask & (1 << i) != 0) == selected)
+                .fold(([0u32; 3], 0), |(sum, count), (_, (r, g, b))| {
+                    (
+                        [sum[0] + *r as u32, sum[1] + *g as u32, sum[2] + *b as u32],
+                        count + 1,
+                    )
+                });
+            (count > 0).then(|| {
+                let [r, g, b] = sum.map(|channel| (channel / count) as u8);
+                (r, g, b)
+            })
+        };
+        foreground = mean(true).unwrap_or(foreground);
+        background = mean(false).unwrap_or(background);
+    }
+
+    (mask, foreground, background)
+}
+
+// Helper to get pixel from flat buffer safely
+fn get_pixel(buffer: &[u8], width: usize, height: usize, x: isize, y: isize) -> Option<&[u8]> {
+    if x < 0 || y < 0 || x >= width as isize || y >= height as isize {
+        return None;
+    }
+    let idx = (y as usize * width + x as usize) * 4;
+    if idx + 4 <= buffer.len() {
+        Some(&buffer[idx..idx + 4])
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::rgba_to_engine;
+
+    #[test]
+    fn presents_cells_which_changed() {
+        let mut renderer = AnsiRenderer::new(
+            AnsiGlyphs::HalfBlock,
+            ColorDepth::TrueColor,
+            Dithering::None,
+        );
+        renderer.resize(1, 1);
+
+        let buffer: Vec<u8> = [[255, 0, 0, 255], [0, 0, 255, 255]]
+            .into_iter()
+            .flat_map(rgba_to_engine)
+            .collect();
+        let frame = Frame {
+            buffer: &buffer,
+            width: 1,
+            height: 2,
+            offset: (0, 0),
+            background: (0, 0, 0),
+        };
+
+        let mut out = vec![];
+        renderer.present(&mut out, &frame).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "\x1b[1;1H\x1b[38;2;255;0;0;48;2;0;0;255m▀\x1b[0m"
+        );
+
+        let mut out = vec![];
+        renderer.present(&mut out, &frame).unwrap();
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn fits_one_color() {
+        let pixels = [(10, 20, 30); 4];
+        assert_eq!(fit_two_colors(&pixels), (0, (10, 20, 30), (10, 20, 30)));
+    }
+
+    #[test]
+    fn fits_the_brighter_color_to_the_foreground() {
+        let pixels = [(0, 0, 0), (250, 250, 250), (255, 255, 255), (5, 5, 5)];
+        assert_eq!(
+            fit_two_colors(&pixels),
+            (0b0110, (252, 252, 252), (2, 2, 2))
+        );
+    }
+
+    #[test]
+    fn fits_two_colors_which_are_as_bright() {
+        // Both have a luma of 38870.
+        let (red, blue) = ((130, 0, 0), (0, 40, 135));
+        let pixels = [red, blue, red, red];
+        assert_eq!(fit_two_colors(&pixels), (0b1101, red, blue));
+    }
+}
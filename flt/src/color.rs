@@ -0,0 +1,409 @@
+//! Maps colors to what the terminal can display, for terminals without
+//! truecolor support.
+
+pub(crate) type Rgb = (u8, u8, u8);
+
+/// A color as it is sent to the terminal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum TerminalColor {
+    Rgb(Rgb),
+    /// An index into the 256 color palette.
+    Ansi256(u8),
+    /// An index into the 16 ANSI colors.
+    Ansi16(u8),
+}
+
+impl TerminalColor {
+    /// The SGR parameters which set this as the foreground color, or the
+    /// background color if `background` is set.
+    ///
+    /// The 16 ANSI colors use the original codes (30-37 and 90-97 for the
+    /// foreground), as `38;5;{n}` isn't supported by terminals with only 16
+    /// colors.
+    pub(crate) fn sgr_parameters(self, background: bool) -> String {
+        let extended = if background { 48 } else { 38 };
+        match self {
+            TerminalColor::Rgb((r, g, b)) => format!("{extended};2;{r};{g};{b}"),
+            TerminalColor::Ansi256(index) => format!("{extended};5;{index}"),
+            TerminalColor::Ansi16(index) => {
+                let base = match (index < 8, background) {
+                    (true, false) => 30,
+                    (true, true) => 40,
+                    (false, false) => 90 - 8,
+                    (false, true) => 100 - 8,
+                };
+                (base + index).to_string()
+            }
+        }
+    }
+}
+
+/// Reads `pixel`, which is in the pixel format of the engine, as RGBA.
+///
+/// The engine uses BGRA on Linux, and RGBA on macOS.
+pub(crate) fn engine_to_rgba(pixel: &[u8]) -> [u8; 4] {
+    if cfg!(target_os = "macos") {
+        [pixel[0], pixel[1], pixel[2], pixel[3]]
+    } else {
+        [pixel[2], pixel[1], pixel[0], pixel[3]]
+    }
+}
+
+/// Converts `rgba` to the pixel format of the engine, see [engine_to_rgba].
+pub(crate) fn rgba_to_engine(rgba: [u8; 4]) -> [u8; 4] {
+    // Swapping the channels is its own inverse.
+    engine_to_rgba(&rgba)
+}
+
+/// Composites `pixel`, which is a premultiplied pixel from the engine, over
+/// `background`.
+pub(crate) fn composite(pixel: &[u8], background: Rgb) -> Rgb {
+    let [r, g, b, a] = engine_to_rgba(pixel);
+    let transparency = 255 - a as u32;
+    let over = |color: u8, background: u8| {
+        (color as u32 + background as u32 * transparency / 255).min(255) as u8
+    };
+    (
+        over(r, background.0),
+        over(g, background.1),
+        over(b, background.2),
+    )
+}
+
+/// Composites every pixel of `buffer`, which is a frame from the engine, over
+/// `background`, keeping the pixel format of the engine.
+pub(crate) fn composite_frame(buffer: &[u8], background: Rgb) -> Vec<u8> {
+    buffer
+        .chunks_exact(4)
+        .flat_map(|pixel| {
+            let (r, g, b) = composite(pixel, background);
+            rgba_to_engine([r, g, b, 255])
+        })
+        .collect()
+}
+
+/// The colors which the terminal can display.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ColorDepth {
+    /// 24-bit colors.
+    #[value(name = "truecolor")]
+    TrueColor,
+    /// The xterm 256 color palette.
+    #[value(name = "256")]
+    Ansi256,
+    /// The 16 ANSI colors, which should work everywhere, including the Linux
+    /// console.
+    #[value(name = "16")]
+    Ansi16,
+}
+
+impl ColorDepth {
+    /// Guesses the color depth from the environment, as terminals can't be
+    /// queried for it reliably.
+    pub(crate) fn detect() -> Self {
+        Self::from_environment(
+            &std::env::var("COLORTERM").unwrap_or_default(),
+            &std::env::var("TERM").unwrap_or_default(),
+        )
+    }
+
+    fn from_environment(colorterm: &str, term: &str) -> Self {
+        if colorterm == "truecolor" || colorterm == "24bit" || term.ends_with("-direct") {
+            ColorDepth::TrueColor
+        } else if term.contains("256color") {
+            ColorDepth::Ansi256
+        } else if matches!(term, "" | "dumb") {
+            // Not a terminal, e.g. a CI log viewer, which are the most likely
+            // to only support the 16 colors.
+            ColorDepth::Ansi16
+        } else if matches!(
+            term,
+            "linux" | "ansi" | "xterm" | "screen" | "tmux" | "rxvt"
+        ) || term.starts_with("vt")
+        {
+            // The terminfo entries of these only have 8 or 16 colors.
+            ColorDepth::Ansi16
+        } else {
+            // Most other terminals in use are modern enough.
+            ColorDepth::TrueColor
+        }
+    }
+
+    /// The closest color to `rgb` which can be displayed.
+    pub(crate) fn to_color(self, rgb: Rgb) -> TerminalColor {
+        match self {
+            ColorDepth::TrueColor => TerminalColor::Rgb(rgb),
+            ColorDepth::Ansi256 => TerminalColor::Ansi256(nearest_ansi256(rgb)),
+            ColorDepth::Ansi16 => TerminalColor::Ansi16(nearest_ansi16(rgb) as u8),
+        }
+    }
+
+    /// The closest displayable color to `rgb`, in RGB.
+    fn quantize(self, rgb: Rgb) -> Rgb {
+        match self {
+            ColorDepth::TrueColor => rgb,
+            ColorDepth::Ansi256 => ansi256_rgb(nearest_ansi256(rgb)),
+            ColorDepth::Ansi16 => ANSI16_RGB[nearest_ansi16(rgb)],
+        }
+    }
+
+    /// The typical distance between adjacent colors in the palette, for each
+    /// channel.
+    fn step(self) -> i32 {
+        match self {
+            ColorDepth::TrueColor => 0,
+            ColorDepth::Ansi256 => 40,
+            ColorDepth::Ansi16 => 128,
+        }
+    }
+}
+
+/// How colors which can't be displayed are approximated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, clap::ValueEnum)]
+pub enum Dithering {
+    /// Use the closest color.
+    #[default]
+    None,
+    /// A 4x4 Bayer matrix, which is stable across frames.
+    Ordered,
+    /// Floyd-Steinberg error diffusion, which is more accurate but may flicker
+    /// in animations.
+    FloydSteinberg,
+}
+
+/// Dithers `pixels`, which is an image of `width` pixels per row, so that every
+/// pixel is a color which `depth` can display.
+pub(crate) fn dither(pixels: &mut [Rgb], width: usize, depth: ColorDepth, dithering: Dithering) {
+    if depth == ColorDepth::TrueColor || width == 0 {
+        return;
+    }
+
+    match dithering {
+        Dithering::None => {
+            for pixel in pixels.iter_mut() {
+                *pixel = depth.quantize(*pixel);
+            }
+        }
+        Dithering::Ordered => {
+            const BAYER: [[i32; 4]; 4] =
+                [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+            for (i, pixel) in pixels.iter_mut().enumerate() {
+                let (x, y) = (i % width, i / width);
+                // Offset by the threshold, which is in (-0.5, 0.5) steps.
+                let offset = (BAYER[y % 4][x % 4] * 2 - 15) * depth.step() / 32;
+                let (r, g, b) = *pixel;
+                let [r, g, b] =
+                    [r, g, b].map(|channel| (channel as i32 + offset).clamp(0, 255) as u8);
+                *pixel = depth.quantize((r, g, b));
+            }
+        }
+        Dithering::FloydSteinberg => {
+            // The quantization error which is carried to the current and next
+            // rows, for each channel of each pixel.
+            let mut errors = vec![[0i32; 3]; width];
+            let mut next_errors = vec![[0i32; 3]; width];
+
+            for row in pixels.chunks_mut(width) {
+                for (x, pixel) in row.iter_mut().enumerate() {
+                    let (r, g, b) = *pixel;
+                    let error = errors[x];
+                    let color = [r, g, b].map(|channel| channel as i32);
+                    let color = [0, 1, 2].map(|i| (color[i] + error[i]).clamp(0, 255));
+
+                    let (qr, qg, qb) =
+                        depth.quantize((color[0] as u8, color[1] as u8, color[2] as u8));
+                    *pixel = (qr, qg, qb);
+
+                    for (channel, value) in [qr, qg, qb].into_iter().enumerate() {
+                        let error = color[channel] - value as i32;
+                        if x + 1 < width {
+                            errors[x + 1][channel] += error * 7 / 16;
+                            next_errors[x + 1][channel] += error / 16;
+                        }
+                        if x > 0 {
+                            next_errors[x - 1][channel] += error * 3 / 16;
+                        }
+                        next_errors[x][channel] += error * 5 / 16;
+                    }
+                }
+
+                std::mem::swap(&mut errors, &mut next_errors);
+                next_errors.fill([0; 3]);
+            }
+        }
+    }
+}
+
+fn distance((r1, g1, b1): Rgb, (r2, g2, b2): Rgb) -> i32 {
+    let d = |a: u8, b: u8| (a as i32 - b as i32).pow(2);
+    d(r1, r2) + d(g1, g2) + d(b1, b2)
+}
+
+/// The levels of each channel in the 6x6x6 color cube of the 256 color palette.
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+/// The closest color in the 256 color palette.
+///
+/// The first 16 colors are skipped, as they are often changed by themes.
+fn nearest_ansi256(rgb: Rgb) -> u8 {
+    let (r, g, b) = rgb;
+    let level = |value: u8| {
+        (0..CUBE_LEVELS.len())
+            .min_by_key(|i| (CUBE_LEVELS[*i] as i32 - value as i32).abs())
+            .unwrap() as u8
+    };
+    let cube = 16 + 36 * level(r) + 6 * level(g) + level(b);
+
+    // The grayscale ramp goes from 8 to 238 in steps of 10.
+    let average = (r as u32 + g as u32 + b as u32) / 3;
+    let gray = 232 + ((average.saturating_sub(3) / 10).min(23)) as u8;
+
+    if distance(ansi256_rgb(gray), rgb) < distance(ansi256_rgb(cube), rgb) {
+        gray
+    } else {
+        cube
+    }
+}
+
+fn ansi256_rgb(index: u8) -> Rgb {
+    match index {
+        0..=15 => ANSI16_RGB[index as usize],
+        16..=231 => {
+            let index = (index - 16) as usize;
+            (
+                CUBE_LEVELS[index / 36],
+                CUBE_LEVELS[index / 6 % 6],
+                CUBE_LEVELS[index % 6],
+            )
+        }
+        _ => {
+            let value = 8 + (index - 232) * 10;
+            (value, value, value)
+        }
+    }
+}
+
+fn nearest_ansi16(rgb: Rgb) -> usize {
+    (0..ANSI16_RGB.len())
+        .min_by_key(|i| distance(ANSI16_RGB[*i], rgb))
+        .unwrap()
+}
+
+/// The 16 ANSI colors, with xterm's default values.
+const ANSI16_RGB: [Rgb; 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn composites_premultiplied_pixels() {
+        // Half transparent red.
+        let pixel = rgba_to_engine([128, 0, 0, 128]);
+        assert_eq!(composite(&pixel, (255, 255, 255)), (255, 127, 127));
+
+        let transparent = rgba_to_engine([0, 0, 0, 0]);
+        assert_eq!(composite(&transparent, (1, 2, 3)), (1, 2, 3));
+    }
+
+    #[test]
+    fn ansi16_colors_use_original_sgr_codes() {
+        assert_eq!(TerminalColor::Ansi16(1).sgr_parameters(false), "31");
+        assert_eq!(TerminalColor::Ansi16(1).sgr_parameters(true), "41");
+        assert_eq!(TerminalColor::Ansi16(9).sgr_parameters(false), "91");
+        assert_eq!(TerminalColor::Ansi16(15).sgr_parameters(true), "107");
+    }
+
+    #[test]
+    fn extended_colors_use_extended_sgr_codes() {
+        assert_eq!(
+            TerminalColor::Ansi256(196).sgr_parameters(false),
+            "38;5;196"
+        );
+        assert_eq!(
+            TerminalColor::Rgb((1, 2, 3)).sgr_parameters(true),
+            "48;2;1;2;3"
+        );
+    }
+
+    #[test]
+    fn maps_to_closest_color() {
+        assert_eq!(
+            ColorDepth::Ansi16.to_color((250, 0, 0)),
+            TerminalColor::Ansi16(9)
+        );
+        assert_eq!(
+            ColorDepth::Ansi256.to_color((255, 0, 0)),
+            TerminalColor::Ansi256(196)
+        );
+        assert_eq!(
+            ColorDepth::TrueColor.to_color((1, 2, 3)),
+            TerminalColor::Rgb((1, 2, 3))
+        );
+    }
+
+    #[test]
+    fn detects_color_depth() {
+        let detect = ColorDepth::from_environment;
+        assert_eq!(detect("truecolor", "xterm"), ColorDepth::TrueColor);
+        assert_eq!(detect("", "xterm-direct"), ColorDepth::TrueColor);
+        assert_eq!(detect("", "xterm-256color"), ColorDepth::Ansi256);
+        assert_eq!(detect("", "linux"), ColorDepth::Ansi16);
+        assert_eq!(detect("", "vt100"), ColorDepth::Ansi16);
+        assert_eq!(detect("", "dumb"), ColorDepth::Ansi16);
+        assert_eq!(detect("", ""), ColorDepth::Ansi16);
+        assert_eq!(detect("", "xterm-kitty"), ColorDepth::TrueColor);
+    }
+
+    fn is_palette_color(depth: ColorDepth, rgb: Rgb) -> bool {
+        match depth {
+            ColorDepth::TrueColor => true,
+            ColorDepth::Ansi256 => (0..=255).any(|index| ansi256_rgb(index) == rgb),
+            ColorDepth::Ansi16 => ANSI16_RGB.contains(&rgb),
+        }
+    }
+
+    #[test]
+    fn dithers_mid_gray_into_palette_colors() {
+        for depth in [ColorDepth::Ansi256, ColorDepth::Ansi16] {
+            for dithering in [Dithering::Ordered, Dithering::FloydSteinberg] {
+                // Halfway between two grays of the 256 color palette.
+                let mut pixels = vec![(153, 153, 153); 64];
+                dither(&mut pixels, 8, depth, dithering);
+
+                let colors: HashSet<_> = pixels.iter().collect();
+                assert!(
+                    colors.iter().all(|rgb| is_palette_color(depth, **rgb)),
+                    "{depth:?} {dithering:?}: {colors:?}"
+                );
+                assert!(colors.len() > 1, "{depth:?} {dithering:?}: {colors:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn quantizes_without_dithering() {
+        let mut pixels = vec![(150, 150, 150); 4];
+        dither(&mut pixels, 2, ColorDepth::Ansi16, Dithering::None);
+        assert_eq!(pixels, [(127, 127, 127); 4]);
+    }
+}
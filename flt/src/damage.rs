@@ -0,0 +1,166 @@
+//! Finds the areas of a frame which changed since the previous frame, so that
+//! only those need to be sent to the terminal.
+
+/// The size of the square tiles which frames are compared in, in pixels.
+const TILE_SIZE: usize = 32;
+
+/// A rectangle in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Rect {
+    pub(crate) x: usize,
+    pub(crate) y: usize,
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+}
+
+impl Rect {
+    pub(crate) fn area(&self) -> usize {
+        self.width * self.height
+    }
+}
+
+/// Compares `previous` and `current`, which are frames of `width` by `height`
+/// pixels with 4 bytes per pixel, and returns the rectangles which cover the
+/// tiles which changed.
+pub(crate) fn damaged_rects(
+    previous: &[u8],
+    current: &[u8],
+    width: usize,
+    height: usize,
+) -> Vec<Rect> {
+    let stride = width * 4;
+    let mut rects: Vec<Rect> = vec![];
+
+    for tile_y in (0..height).step_by(TILE_SIZE) {
+        let tile_height = TILE_SIZE.min(height - tile_y);
+        let rows = tile_y * stride..(tile_y + tile_height) * stride;
+        let (previous_rows, current_rows) = (&previous[rows.clone()], &current[rows]);
+
+        let is_damaged = |tile_x: usize| {
+            let columns = tile_x * 4..(tile_x + TILE_SIZE.min(width - tile_x)) * 4;
+            previous_rows
+                .chunks_exact(stride)
+                .zip(current_rows.chunks_exact(stride))
+                .any(|(previous, current)| previous[columns.clone()] != current[columns.clone()])
+        };
+
+        // Damaged tiles next to each other in a row are merged.
+        let mut row_rects: Vec<Rect> = vec![];
+        for tile_x in (0..width).step_by(TILE_SIZE).filter(|x| is_damaged(*x)) {
+            let tile_width = TILE_SIZE.min(width - tile_x);
+            match row_rects.last_mut() {
+                Some(rect) if rect.x + rect.width == tile_x => rect.width += tile_width,
+                _ => row_rects.push(Rect {
+                    x: tile_x,
+                    y: tile_y,
+                    width: tile_width,
+                    height: tile_height,
+                }),
+            }
+        }
+
+        // Then rects spanning the same columns in the previous row of tiles.
+        for rect in row_rects {
+            match rects.iter_mut().find(|above| {
+                above.x == rect.x && above.width == rect.width && above.y + above.height == tile_y
+            }) {
+                Some(above) => above.height += rect.height,
+                None => rects.push(rect),
+            }
+        }
+    }
+
+    rects
+}
+
+/// Copies the pixels in `rect` out of `buffer`, which is a frame `width` pixels
+/// wide with 4 bytes per pixel.
+pub(crate) fn crop(buffer: &[u8], width: usize, rect: Rect) -> Vec<u8> {
+    let stride = width * 4;
+    (rect.y..rect.y + rect.height)
+        .flat_map(|y| {
+            let start = y * stride + rect.x * 4;
+            &buffer[start..start + rect.width * 4]
+        })
+        .copied()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: usize = 100;
+    const HEIGHT: usize = 70;
+
+    /// Compares an empty frame with one where the pixels at `changed` are set.
+    fn damaged_pixels(changed: &[(usize, usize)]) -> Vec<Rect> {
+        let previous = vec![0; WIDTH * HEIGHT * 4];
+        let mut current = previous.clone();
+        for (x, y) in changed {
+            current[(y * WIDTH + x) * 4] = 255;
+        }
+        damaged_rects(&previous, &current, WIDTH, HEIGHT)
+    }
+
+    fn rect(x: usize, y: usize, width: usize, height: usize) -> Rect {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn finds_no_damage_in_the_same_frame() {
+        assert_eq!(damaged_pixels(&[]), vec![]);
+    }
+
+    #[test]
+    fn finds_damaged_tiles() {
+        assert_eq!(damaged_pixels(&[(40, 5)]), vec![rect(32, 0, 32, 32)]);
+    }
+
+    #[test]
+    fn clips_tiles_to_the_frame() {
+        assert_eq!(damaged_pixels(&[(99, 69)]), vec![rect(96, 64, 4, 6)]);
+    }
+
+    #[test]
+    fn merges_tiles_in_a_row() {
+        assert_eq!(
+            damaged_pixels(&[(0, 0), (33, 0), (70, 0)]),
+            vec![rect(0, 0, 96, 32)]
+        );
+    }
+
+    #[test]
+    fn merges_rects_spanning_the_same_columns() {
+        assert_eq!(
+            damaged_pixels(&[(0, 0), (33, 0), (0, 40), (33, 40), (0, 69), (33, 69)]),
+            vec![rect(0, 0, 64, 70)]
+        );
+    }
+
+    #[test]
+    fn keeps_rects_spanning_other_columns() {
+        assert_eq!(
+            damaged_pixels(&[(0, 0), (0, 40), (33, 40)]),
+            vec![rect(0, 0, 32, 32), rect(0, 32, 64, 32)]
+        );
+        assert_eq!(
+            damaged_pixels(&[(0, 0), (70, 0)]),
+            vec![rect(0, 0, 32, 32), rect(64, 0, 32, 32)]
+        );
+    }
+
+    #[test]
+    fn crops_rects() {
+        let buffer: Vec<u8> = (0..3 * 2 * 4).collect();
+        assert_eq!(
+            crop(&buffer, 3, rect(1, 1, 2, 1)),
+            (16..24).collect::<Vec<_>>()
+        );
+    }
+}
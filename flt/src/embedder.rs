@@ -1,9 +1,18 @@
-use crate::constants::FPS;
+use crate::ansi::AnsiGlyphs;
+use crate::color::{ColorDepth, Dithering};
 use crate::event::{EngineEvent, PlatformEvent};
+use crate::frame_pacer::FramePacer;
+use crate::keyboard::KeyboardState;
+use crate::lifecycle::{send_lifecycle_state, AppLifecycleState};
+use crate::mouse_cursor_plugin::MouseCursorPlugin;
+use crate::platform_plugin::PlatformPlugin;
+use crate::pointer::PointerState;
+use crate::renderer::RendererKind;
 use crate::semantics::FlutterSemanticsTree;
 use crate::task_runner::TaskRunner;
 use crate::terminal_window::TerminalWindow;
 use crate::Error;
+use flutter_sys::text_input::TextInputPlugin;
 use flutter_sys::{sys, Callbacks, FlutterEngine};
 #[cfg(target_os = "macos")]
 use metal::foreign_types::ForeignType;
@@ -11,7 +20,7 @@ use metal::foreign_types::ForeignType;
 use metal::{Device, MTLPixelFormat, MTLTextureUsage, TextureDescriptor};
 use std::ffi::c_void;
 use std::mem;
-use std::sync::mpsc::{channel, Receiver};
+use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::{Arc, Mutex};
 use std::thread;
 
@@ -24,11 +33,21 @@ pub struct TerminalEmbedder {
     // TODO(jiahaog): This should be a path instead.
     pub(crate) debug_semantics: bool,
     pub(crate) show_semantics: bool,
+    pub(crate) scroll_delta: f64,
 
     // Event related.
     pub(crate) should_run: bool,
     pub(crate) platform_events: Receiver<PlatformEvent>,
+    pub(crate) platform_events_sender: Sender<PlatformEvent>,
     pub(crate) platform_task_runner: TaskRunner,
+    pub(crate) frame_pacer: FramePacer,
+    pub(crate) keyboard: KeyboardState,
+    pub(crate) pointer: PointerState,
+
+    // Plugins.
+    pub(crate) text_input: TextInputPlugin,
+    pub(crate) platform: PlatformPlugin,
+    pub(crate) mouse_cursor: MouseCursorPlugin,
 
     // Window related.
     pub(crate) dimensions: (usize, usize),
@@ -48,9 +67,21 @@ impl TerminalEmbedder {
         log_events: bool,
         debug_semantics: bool,
         disable_kitty: bool,
+        renderer: Option<RendererKind>,
+        ansi_glyphs: AnsiGlyphs,
+        color_depth: Option<ColorDepth>,
+        dithering: Dithering,
         disable_gpu: bool,
+        scroll_delta: f64,
+        max_fps: f64,
         log_file: Option<String>,
     ) -> Result<Self, Error> {
+        if !max_fps.is_finite() || max_fps <= 0.0 {
+            return Err(Error::GenericError(format!(
+                "The maximum frame rate must be positive, got {max_fps}"
+            )));
+        }
+
         let (main_sender, main_receiver) = channel();
 
         let terminal_window = TerminalWindow::new(
@@ -58,11 +89,17 @@ impl TerminalEmbedder {
             alternate_screen,
             log_events,
             disable_kitty,
+            renderer,
+            ansi_glyphs,
+            color_depth,
+            dithering,
             main_sender.clone(),
             log_file,
         );
 
-        let (sender_a, sender_b, sender_c, sender_d, sender_e) = (
+        let (sender_a, sender_b, sender_c, sender_d, sender_e, sender_f, sender_g) = (
+            main_sender.clone(),
+            main_sender.clone(),
             main_sender.clone(),
             main_sender.clone(),
             main_sender.clone(),
@@ -113,6 +150,18 @@ impl TerminalEmbedder {
                     .unwrap();
             });
 
+        let on_pre_engine_restart_callback: Box<dyn Fn()> = Box::new(move || {
+            sender_f
+                .send(PlatformEvent::EngineEvent(EngineEvent::PreEngineRestart))
+                .unwrap();
+        });
+
+        let vsync_callback: Box<dyn Fn(flutter_sys::VsyncBaton)> = Box::new(move |baton| {
+            sender_g
+                .send(PlatformEvent::EngineEvent(EngineEvent::Vsync(baton)))
+                .unwrap();
+        });
+
         // This draw callback is ONLY used for software rendering.
         // For Metal, we use present_drawable_callback.
         let sender_d_software = sender_d.clone();
@@ -211,8 +260,12 @@ kernel void copy_texture_to_buffer(texture2d<float, access::read> inputTexture [
 
                     platform_message_callback: Some(platform_message_callback),
 
+                    on_pre_engine_restart_callback: Some(on_pre_engine_restart_callback),
+
                     draw_callback: None, // Metal doesn't use this
 
+                    vsync_callback: Some(vsync_callback),
+
                     get_next_drawable_callback: Some(Box::new(move |frame_info| {
                         let width = frame_info.size.width as u64;
                         let height = frame_info.size.height as u64;
@@ -353,9 +406,11 @@ kernel void copy_texture_to_buffer(texture2d<float, access::read> inputTexture [
                     log_message_callback: Some(log_message_callback),
                     update_semantics_callback: Some(update_semantics_callback),
                     platform_message_callback: Some(platform_message_callback),
+                    on_pre_engine_restart_callback: Some(on_pre_engine_restart_callback),
                     draw_callback: Some(software_draw_callback),
                     get_next_drawable_callback: None,
                     present_drawable_callback: None,
+                    vsync_callback: Some(vsync_callback),
                 };
                 (callbacks, None, None)
             }
@@ -371,9 +426,11 @@ kernel void copy_texture_to_buffer(texture2d<float, access::read> inputTexture [
                 log_message_callback: Some(log_message_callback),
                 update_semantics_callback: Some(update_semantics_callback),
                 platform_message_callback: Some(platform_message_callback),
+                on_pre_engine_restart_callback: Some(on_pre_engine_restart_callback),
                 draw_callback: Some(software_draw_callback),
                 get_next_drawable_callback: None,
                 present_drawable_callback: None,
+                vsync_callback: Some(vsync_callback),
             };
             (callbacks, None, None)
         };
@@ -392,9 +449,17 @@ kernel void copy_texture_to_buffer(texture2d<float, access::read> inputTexture [
             semantics_tree: FlutterSemanticsTree::new(),
             debug_semantics,
             show_semantics: false,
+            scroll_delta,
             should_run: true,
             platform_events: main_receiver,
+            platform_events_sender: main_sender.clone(),
             platform_task_runner: TaskRunner::new(),
+            frame_pacer: FramePacer::new(max_fps),
+            keyboard: KeyboardState::default(),
+            pointer: PointerState::default(),
+            text_input: TextInputPlugin::new(),
+            platform: PlatformPlugin::new(),
+            mouse_cursor: MouseCursorPlugin::new(),
             dimensions: (0, 0),
             zoom: 1.0,
             scale: 1.0,
@@ -404,12 +469,15 @@ kernel void copy_texture_to_buffer(texture2d<float, access::read> inputTexture [
         };
 
         embedder.engine.notify_display_update(
-            FPS as f64,
+            max_fps,
             (width, height),
             embedder.terminal_window.device_pixel_ratio(),
         )?;
         embedder.reset_viewport()?;
 
+        // The framework has no lifecycle state until the embedder reports one.
+        send_lifecycle_state(&embedder.engine, AppLifecycleState::Resumed)?;
+
         // This event sets the engine window dimensions which will kickstart rendering.
         main_sender
             .send(PlatformEvent::EngineEvent(EngineEvent::Draw(vec![], 0, 0)))
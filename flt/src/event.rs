@@ -1,13 +1,24 @@
-use crate::{Error, TerminalEmbedder};
-use flutter_sys::{EngineTask, SemanticsUpdate};
+use crate::lifecycle::{send_lifecycle_state, AppLifecycleState};
+use crate::{mouse_cursor_plugin, platform_plugin, Error, TerminalEmbedder};
+use flutter_sys::{text_input, EngineTask, SemanticsUpdate};
 use std::fs::File;
 use std::io::Write;
+use std::time::Duration;
 
 /// Events that should be handled on the platform (main) thread.
 #[derive(Debug)]
 pub(crate) enum PlatformEvent {
     EngineEvent(EngineEvent),
     TerminalEvent(crossterm::event::Event),
+    /// The render thread finished drawing a frame, in the given duration.
+    FrameDrawn(Result<Duration, std::io::Error>),
+    /// The user asked to suspend the process, e.g. with Ctrl + z or SIGTSTP.
+    SuspendRequested,
+    /// The framework handled the paused lifecycle state, so the process can be
+    /// suspended.
+    ReadyToSuspend,
+    /// The process was continued after being stopped, e.g. with SIGCONT.
+    Continued,
 }
 
 #[derive(Debug)]
@@ -15,16 +26,39 @@ pub(crate) enum EngineEvent {
     UpdateSemantics(Vec<SemanticsUpdate>),
     Draw(Vec<u8>, usize, usize),
     EngineTask(EngineTask),
-    LogMessage { tag: String, message: String },
+    LogMessage {
+        tag: String,
+        message: String,
+    },
     PlatformMessage(flutter_sys::PlatformMessage),
+    /// The framework did not handle a key event, so it should be handled by
+    /// the embedder.
+    KeyEventUnhandled(crossterm::event::KeyEvent),
+    /// The engine is about to restart, e.g. on hot restart.
+    PreEngineRestart,
+    /// The engine is ready to produce a frame once the vsync is sent.
+    Vsync(flutter_sys::VsyncBaton),
 }
 
 impl TerminalEmbedder {
     pub fn run_event_loop(&mut self) -> Result<(), Error> {
         // TODO(jiahaog): Consider async Rust or Tokio instead.
-        // TODO(jiahaog): It is a mistake to handle input events and drawing on the same thread.
         while self.should_run {
-            if let Ok(platform_task) = self.platform_events.recv() {
+            // Wake up in time for the next engine task, e.g. for Dart timers,
+            // or the next frame, even if no other events arrive.
+            let timeout = match (
+                self.platform_task_runner.time_until_next_task(),
+                self.frame_pacer.time_until_vsync(),
+            ) {
+                (Some(task), Some(vsync)) => Some(task.min(vsync)),
+                (task, vsync) => task.or(vsync),
+            };
+            let platform_event = match timeout {
+                Some(timeout) => self.platform_events.recv_timeout(timeout).ok(),
+                None => self.platform_events.recv().ok(),
+            };
+
+            if let Some(platform_task) = platform_event {
                 match platform_task {
                     PlatformEvent::EngineEvent(EngineEvent::UpdateSemantics(updates)) => {
                         self.semantics_tree.update(updates);
@@ -59,20 +93,66 @@ impl TerminalEmbedder {
                         self.terminal_window.log(format!("{tag}: {message}"));
                     }
                     PlatformEvent::EngineEvent(EngineEvent::PlatformMessage(message)) => {
-                        if !flutter_sys::text_input::handle_message(&message) {
-                            self.engine
-                                .send_platform_message_response(message.response_handle, None)?;
-                        }
+                        let response = match message.channel.as_str() {
+                            text_input::CHANNEL => self.text_input.handle_message(&message),
+                            platform_plugin::CHANNEL => self
+                                .platform
+                                .handle_message(&mut self.terminal_window, &message.message),
+                            mouse_cursor_plugin::CHANNEL => self
+                                .mouse_cursor
+                                .handle_message(&mut self.terminal_window, &message.message),
+                            _ => None,
+                        };
+                        self.engine.send_platform_message_response(
+                            message.response_handle,
+                            response.as_deref(),
+                        )?;
+                    }
+                    PlatformEvent::EngineEvent(EngineEvent::PreEngineRestart) => {
+                        self.text_input.reset();
+                    }
+                    PlatformEvent::EngineEvent(EngineEvent::Vsync(baton)) => {
+                        self.frame_pacer.request_vsync(baton);
+                    }
+                    PlatformEvent::EngineEvent(EngineEvent::KeyEventUnhandled(event)) => {
+                        self.handle_unhandled_key_event(event)?;
                     }
                     PlatformEvent::TerminalEvent(event) => {
                         self.handle_terminal_event(event)?;
                     }
+                    PlatformEvent::SuspendRequested => {
+                        self.request_suspend()?;
+                    }
+                    PlatformEvent::ReadyToSuspend => {
+                        self.terminal_window.suspend()?;
+                        send_lifecycle_state(&self.engine, AppLifecycleState::Resumed)?;
+                        self.engine.schedule_frame()?;
+                    }
+                    PlatformEvent::Continued => {
+                        self.terminal_window.continued()?;
+                        self.engine.schedule_frame()?;
+                    }
+                    PlatformEvent::FrameDrawn(Ok(duration)) => {
+                        self.frame_pacer.frame_drawn(duration);
+                        self.terminal_window.frame_drawn(duration);
+                    }
+                    PlatformEvent::FrameDrawn(Err(error)) => {
+                        // There is no terminal to draw to anymore.
+                        if error.kind() == std::io::ErrorKind::BrokenPipe {
+                            return Err(error.into());
+                        }
+                        // Otherwise, e.g. when the terminal is busy, the next
+                        // frame might work. It is drawn in full, as this one
+                        // might have been cut short.
+                        self.terminal_window
+                            .log(format!("Failed to draw a frame: {error}"));
+                        self.terminal_window.mark_dirty();
+                    }
                 };
             }
 
-            // TODO(jiahaog): Doing it like this probably makes us only able to run expired
-            // tasks when a platform event is received.
             self.platform_task_runner.run_expired_tasks(&self.engine)?;
+            self.frame_pacer.send_vsync_if_due(&self.engine)?;
         }
 
         Ok(())
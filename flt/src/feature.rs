@@ -1,6 +1,7 @@
+use base64::prelude::*;
 use crossterm::{style::Print, ExecutableCommand};
 use libc::{poll, pollfd, POLLIN, STDIN_FILENO};
-use std::io::{Read, Stdout, Write};
+use std::io::{Stdout, Write};
 use std::time::{Duration, Instant};
 
 /// Checks if the terminal supports the Kitty graphics protocol.
@@ -16,17 +17,322 @@ pub fn kitty_graphics_supported(stdout: &mut Stdout) -> bool {
     // a=q      : Action = query. We are asking the terminal if it supports graphics.
     // ;        : Separator between keys and payload (payload is empty here).
     // \x1b\\   : ST (String Terminator). Ends the command.
-    let _ = stdout.execute(Print("\x1b_Gi=31,a=q;\x1b\\"));
+    let response = query_terminal(stdout, "\x1b_Gi=31,a=q;\x1b\\", |response| {
+        // The terminal should respond with something containing "i=31" if it understood the query.
+        // e.g., \x1b_Gi=31;OK\x1b\
+        // If we encounter the String Terminator, we stop reading.
+        response.contains("i=31") || response.ends_with("\x1b\\")
+    });
+    response.contains("i=31")
+}
+
+/// Checks if the terminal can read an image with the kitty graphics protocol
+/// through `medium`, e.g. `s` for shared memory, where `payload` is the
+/// base64 encoded location of a 1x1 RGBA image.
+///
+/// Terminals can't read shared memory or files from other machines, e.g. over
+/// SSH.
+pub fn kitty_transmission_supported(stdout: &mut Stdout, medium: char, payload: &str) -> bool {
+    // \x1b_G...\x1b\\ : Query whether the image can be transmitted.
+    // i=32           : ID = 32, to identify the reply.
+    // s=1,v=1,f=32   : A 1x1 RGBA image.
+    // a=q            : Action = query, so the image isn't stored.
+    // t={medium}     : The transmission medium.
+    // \x1b[c         : Primary device attributes (DA1), as a sentinel for
+    //                  terminals which don't reply.
+    let query = format!(
+        "\x1b_Gi=32,s=1,v=1,f=32,a=q,t={};{}\x1b\\\x1b[c",
+        medium, payload
+    );
+    let response = query_terminal(stdout, &query, is_primary_device_attributes);
+    response.contains("i=32;OK")
+}
+
+/// Checks if the terminal supports editing the frames of images with the kitty
+/// graphics protocol, which is part of its animation support.
+///
+/// Terminals which support transmitting images but not animations might
+/// ignore such edits without an error.
+pub fn kitty_frame_editing_supported(stdout: &mut Stdout) -> bool {
+    // \x1b_G...\x1b\\ : Store a 1x1 RGBA image with ID 33, quietly.
+    // \x1b_G...\x1b\\ : Replace the pixel of its root frame (a=f,r=1,X=1),
+    //                  which the terminal replies to as `q` isn't set.
+    // \x1b_G...\x1b\\ : Delete the image, quietly.
+    // \x1b[c         : Primary device attributes (DA1), as a sentinel for
+    //                  terminals which don't reply.
+    let response = query_terminal(
+        stdout,
+        "\x1b_Ga=t,t=d,i=33,s=1,v=1,f=32,q=2;AAAAAA==\x1b\\\
+         \x1b_Ga=f,t=d,i=33,r=1,s=1,v=1,f=32,X=1;AAAAAA==\x1b\\\
+         \x1b_Ga=d,d=I,i=33,q=2\x1b\\\
+         \x1b[c",
+        is_primary_device_attributes,
+    );
+    response.contains("i=33;OK")
+}
+
+/// Checks if the terminal supports the iTerm2 inline images protocol, by
+/// identifying the terminal.
+///
+/// See https://iterm2.com/documentation-images.html.
+pub fn iterm2_images_supported(stdout: &mut Stdout) -> bool {
+    // Set by the terminal, but it isn't forwarded over SSH.
+    let term_program = std::env::var("TERM_PROGRAM").unwrap_or_default();
+    if term_program == "iTerm.app" || term_program == "WezTerm" {
+        return true;
+    }
+
+    // \x1b[>0q : Request the name and version of the terminal (XTVERSION).
+    // \x1b[c   : Primary device attributes (DA1), as a sentinel for terminals
+    //            which don't reply.
+    //
+    // Terminals reply with `\x1bP>|{name} {version}\x1b\\`.
+    let response = query_terminal(stdout, "\x1b[>0q\x1b[c", is_primary_device_attributes);
+    is_iterm2_compatible(&response)
+}
+
+/// Whether the terminal named in the XTVERSION reply in `response` supports
+/// the iTerm2 inline images protocol.
+fn is_iterm2_compatible(response: &str) -> bool {
+    const TERMINALS: [&str; 2] = ["iTerm2", "WezTerm"];
+
+    response
+        .split("\x1bP>|")
+        .skip(1)
+        .any(|reply| TERMINALS.iter().any(|terminal| reply.starts_with(terminal)))
+}
+
+/// Checks if the terminal supports Sixel graphics, which it reports as
+/// attribute 4 in its reply to a primary device attributes query.
+pub fn sixel_supported(stdout: &mut Stdout) -> bool {
+    // \x1b[c : Primary device attributes (DA1).
+    let response = query_terminal(stdout, "\x1b[c", is_primary_device_attributes);
+    has_sixel_attribute(&response)
+}
+
+/// Whether the primary device attributes in `response` include Sixel graphics.
+fn has_sixel_attribute(response: &str) -> bool {
+    csi_replies(response)
+        .filter_map(|reply| reply.strip_suffix('c'))
+        .any(|attributes| {
+            attributes
+                .split(';')
+                .skip(1)
+                .any(|attribute| attribute == "4")
+        })
+}
+
+/// Checks if the terminal supports the kitty keyboard protocol.
+///
+/// See https://sw.kovidgoyal.net/kitty/keyboard-protocol/#detection-of-support-for-this-protocol.
+pub fn kitty_keyboard_supported(stdout: &mut Stdout) -> bool {
+    // \x1b[?u : Query the current progressive enhancement flags.
+    // \x1b[c  : Primary device attributes (DA1), which all terminals respond to.
+    //
+    // Terminals supporting the protocol reply with `\x1b[?{flags}u` before the
+    // reply to DA1, so we don't need to wait for the timeout on terminals
+    // which don't support it.
+    let response = query_terminal(stdout, "\x1b[?u\x1b[c", is_primary_device_attributes);
+    has_keyboard_flags(&response)
+}
+
+/// Whether `response` has a reply with the progressive enhancement flags of
+/// the kitty keyboard protocol.
+fn has_keyboard_flags(response: &str) -> bool {
+    csi_replies(response).any(|reply| {
+        reply.ends_with('u') && reply[..reply.len() - 1].chars().all(|c| c.is_ascii_digit())
+    })
+}
+
+/// Checks if the terminal supports reporting mouse events in pixels rather
+/// than cells, with the SGR-Pixels mode (DECSET 1016).
+pub fn sgr_pixels_supported(stdout: &mut Stdout) -> bool {
+    private_mode_supported(stdout, 1016)
+}
+
+/// Checks if the terminal supports synchronized output (DECSET 2026), where
+/// the screen isn't updated until the end of a frame.
+///
+/// See https://gist.github.com/christianparpart/d8a62cc1ab659194337d73e399004036.
+pub fn synchronized_output_supported(stdout: &mut Stdout) -> bool {
+    private_mode_supported(stdout, 2026)
+}
+
+/// Checks if the terminal supports the DEC private `mode`.
+fn private_mode_supported(stdout: &mut Stdout, mode: u16) -> bool {
+    // \x1b[?{mode}$p : Request the state of the mode (DECRQM).
+    // \x1b[c         : Primary device attributes (DA1), as a sentinel for
+    //                  terminals which don't reply.
+    //
+    // Terminals reply with `\x1b[?{mode};{state}$y`, where a state of 0 means
+    // the mode is not recognized, and 4 means it is permanently reset.
+    let query = format!("\x1b[?{}$p\x1b[c", mode);
+    let response = query_terminal(stdout, &query, is_primary_device_attributes);
+    is_mode_supported(&response, mode)
+}
+
+/// Whether `response` has a DECRQM reply for `mode` which is neither
+/// unrecognized nor permanently reset.
+fn is_mode_supported(response: &str, mode: u16) -> bool {
+    let prefix = format!("{};", mode);
+    csi_replies(response).any(|reply| {
+        reply
+            .strip_prefix(prefix.as_str())
+            .and_then(|reply| reply.strip_suffix("$y"))
+            .is_some_and(|state| matches!(state, "1" | "2" | "3"))
+    })
+}
+
+/// Checks if the terminal supports setting the shape of the mouse pointer with
+/// OSC 22.
+///
+/// See https://sw.kovidgoyal.net/kitty/pointer-shapes/.
+pub fn pointer_shape_supported(stdout: &mut Stdout) -> bool {
+    // Terminals which support setting the shape but not querying it.
+    let term = std::env::var("TERM").unwrap_or_default();
+    let term_program = std::env::var("TERM_PROGRAM").unwrap_or_default();
+    if term.starts_with("foot") || term_program == "ghostty" {
+        return true;
+    }
+
+    // \x1b]22;?__current__\x1b\\ : Query the current pointer shape.
+    // \x1b[c                     : Primary device attributes (DA1), as a sentinel
+    //                              for terminals which don't reply.
+    let response = query_terminal(
+        stdout,
+        "\x1b]22;?__current__\x1b\\\x1b[c",
+        is_primary_device_attributes,
+    );
+    response.contains("\x1b]22;")
+}
+
+/// Queries the background color of the terminal with OSC 11.
+pub fn query_background_color(stdout: &mut Stdout) -> Option<(u8, u8, u8)> {
+    // \x1b]11;?\x1b\\ : Request the background color.
+    // \x1b[c         : Primary device attributes (DA1), as a sentinel for
+    //                  terminals which don't reply.
+    //
+    // Terminals reply with `\x1b]11;rgb:{r}/{g}/{b}` followed by a terminator,
+    // where each channel is 1 to 4 hex digits.
+    let response = query_terminal(
+        stdout,
+        "\x1b]11;?\x1b\\\x1b[c",
+        is_primary_device_attributes,
+    );
+    parse_background_color(&response)
+}
+
+/// Parses the OSC 11 reply in `response`.
+fn parse_background_color(response: &str) -> Option<(u8, u8, u8)> {
+    let (_, reply) = response.split_once("\x1b]11;rgb:")?;
+    let reply = reply.split(['\x1b', '\x07']).next()?;
+
+    let mut channels = reply.split('/').map(|channel| {
+        if channel.is_empty() || channel.len() > 4 {
+            return None;
+        }
+        let value = u32::from_str_radix(channel, 16).ok()?;
+        let max = 16u32.pow(channel.len() as u32) - 1;
+        Some((value * 255 / max) as u8)
+    });
+    Some((channels.next()??, channels.next()??, channels.next()??))
+}
+
+/// Whether `response` ends with a reply to a primary device attributes query,
+/// e.g. `\x1b[?62;4;22c`.
+fn is_primary_device_attributes(response: &str) -> bool {
+    csi_replies(response)
+        .last()
+        .filter(|reply| reply.ends_with('c'))
+        .is_some()
+}
+
+/// Splits `response` into the parameters and final byte of each `\x1b[?`
+/// reply in it.
+fn csi_replies(response: &str) -> impl Iterator<Item = &str> {
+    response.split("\x1b[?").skip(1)
+}
+
+/// How long to wait for the terminal to reply with the clipboard, which blocks
+/// the thread reading it. Terminals might ask the user before replying, so
+/// this is longer than for other queries.
+pub const CLIPBOARD_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// The result of reading the clipboard with [query_clipboard].
+#[derive(Debug, PartialEq)]
+pub enum ClipboardReply {
+    Text(String),
+    /// The terminal didn't reply, which is the case when it doesn't support
+    /// (or allow) reading the clipboard.
+    Unsupported,
+    /// The terminal replied, but the contents couldn't be decoded.
+    Invalid,
+}
+
+/// Reads the clipboard with OSC 52.
+pub fn query_clipboard(stdout: &mut Stdout) -> ClipboardReply {
+    // \x1b]52;c;?\x1b\\ : Request the contents of the clipboard.
+    let response = query_terminal_with_timeout(
+        stdout,
+        "\x1b]52;c;?\x1b\\",
+        CLIPBOARD_TIMEOUT,
+        is_osc_complete,
+    );
+    parse_clipboard_reply(&response)
+}
+
+/// Whether `response` ends with the terminator of an OSC reply, which is
+/// either ST or BEL.
+fn is_osc_complete(response: &str) -> bool {
+    response.ends_with("\x1b\\") || response.ends_with('\x07')
+}
+
+fn parse_clipboard_reply(response: &str) -> ClipboardReply {
+    // The reply is `\x1b]52;c;{base64 contents}` followed by a terminator.
+    let Some((_, payload)) = response.split_once("\x1b]52;") else {
+        return ClipboardReply::Unsupported;
+    };
+    if !is_osc_complete(payload) {
+        // Timed out in the middle of the reply.
+        return ClipboardReply::Invalid;
+    }
+    let payload = payload.trim_end_matches("\x1b\\").trim_end_matches('\x07');
+    let Some((_, payload)) = payload.split_once(';') else {
+        return ClipboardReply::Invalid;
+    };
+
+    BASE64_STANDARD
+        .decode(payload)
+        .ok()
+        .and_then(|contents| String::from_utf8(contents).ok())
+        .map_or(ClipboardReply::Invalid, ClipboardReply::Text)
+}
+
+/// Writes `query` to the terminal, and reads from stdin until `is_complete`
+/// returns true for everything read so far, or until a timeout.
+///
+/// This must be called in raw mode and while nothing else is reading from
+/// stdin.
+fn query_terminal(stdout: &mut Stdout, query: &str, is_complete: impl Fn(&str) -> bool) -> String {
+    // We wait up to 100ms for a response.
+    query_terminal_with_timeout(stdout, query, Duration::from_millis(100), is_complete)
+}
+
+fn query_terminal_with_timeout(
+    stdout: &mut Stdout,
+    query: &str,
+    timeout: Duration,
+    is_complete: impl Fn(&str) -> bool,
+) -> String {
+    let _ = stdout.execute(Print(query));
     let _ = stdout.flush();
 
-    let mut buf = [0u8; 1024];
-    let mut idx = 0;
+    let mut response = vec![];
+    let mut buf = [0u8; 4096];
     let start = Instant::now();
-    let mut detected = false;
 
-    // We wait up to 100ms for a response.
     loop {
-        if start.elapsed() > Duration::from_millis(100) {
+        if start.elapsed() > timeout {
             break;
         }
 
@@ -38,26 +344,142 @@ pub fn kitty_graphics_supported(stdout: &mut Stdout) -> bool {
 
         let ret = unsafe { poll(fds.as_mut_ptr(), 1, 10) };
         if ret > 0 {
-            // Data available
-            let read_bytes = std::io::stdin().read(&mut buf[idx..]);
-            match read_bytes {
-                Ok(n) if n > 0 => {
-                    idx += n;
-                    let s = String::from_utf8_lossy(&buf[..idx]);
-                    // The terminal should respond with something containing "i=31" if it understood the query.
-                    // e.g., \x1b_Gi=31;OK\x1b\
-                    if s.contains("i=31") {
-                        detected = true;
-                        break;
-                    }
-                    // If we encounter the String Terminator, we stop reading.
-                    if s.ends_with("\x1b\\") {
-                        break;
-                    }
+            // Read the file descriptor directly, as `std::io::stdin()` is
+            // buffered, so it could consume more than is available according
+            // to `poll`, and keep bytes which other readers (i.e. crossterm)
+            // wouldn't see.
+            let n = unsafe { libc::read(STDIN_FILENO, buf.as_mut_ptr().cast(), buf.len()) };
+            if n > 0 {
+                response.extend_from_slice(&buf[..n as usize]);
+                if is_complete(&String::from_utf8_lossy(&response)) {
+                    break;
                 }
-                _ => break,
+            } else if n == 0
+                || !matches!(
+                    std::io::Error::last_os_error().kind(),
+                    std::io::ErrorKind::Interrupted | std::io::ErrorKind::WouldBlock
+                )
+            {
+                break;
             }
         }
     }
-    detected
+    String::from_utf8_lossy(&response).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_clipboard_reply() {
+        assert_eq!(
+            parse_clipboard_reply("\x1b]52;c;aGVsbG8=\x1b\\"),
+            ClipboardReply::Text("hello".to_string())
+        );
+        assert_eq!(
+            parse_clipboard_reply("\x1b]52;c;aGVsbG8=\x07"),
+            ClipboardReply::Text("hello".to_string())
+        );
+        assert_eq!(
+            parse_clipboard_reply("\x1b]52;c;\x1b\\"),
+            ClipboardReply::Text(String::new())
+        );
+    }
+
+    #[test]
+    fn parses_large_clipboard_reply() {
+        let text = "0123456789".repeat(1000);
+        let reply = format!("\x1b]52;c;{}\x1b\\", BASE64_STANDARD.encode(&text));
+        assert_eq!(parse_clipboard_reply(&reply), ClipboardReply::Text(text));
+    }
+
+    #[test]
+    fn truncated_clipboard_reply_is_invalid() {
+        assert_eq!(
+            parse_clipboard_reply("\x1b]52;c;aGVsbG8="),
+            ClipboardReply::Invalid
+        );
+        assert_eq!(
+            parse_clipboard_reply("\x1b]52;c;not base64!\x1b\\"),
+            ClipboardReply::Invalid
+        );
+    }
+
+    #[test]
+    fn no_clipboard_reply_is_unsupported() {
+        assert_eq!(parse_clipboard_reply(""), ClipboardReply::Unsupported);
+        assert_eq!(
+            parse_clipboard_reply("\x1b[?62;4c"),
+            ClipboardReply::Unsupported
+        );
+    }
+
+    #[test]
+    fn finds_primary_device_attributes_at_the_end() {
+        assert!(is_primary_device_attributes("\x1b[?62;4;22c"));
+        assert!(is_primary_device_attributes("\x1b[?1u\x1b[?62c"));
+        assert!(!is_primary_device_attributes("\x1b[?62c\x1b[?1u"));
+        assert!(!is_primary_device_attributes("\x1b[?62;4"));
+        assert!(!is_primary_device_attributes(""));
+    }
+
+    #[test]
+    fn splits_csi_replies() {
+        let replies: Vec<_> = csi_replies("\x1b[?1u\x1b[?2026;2$y\x1b[?62c").collect();
+        assert_eq!(replies, ["1u", "2026;2$y", "62c"]);
+    }
+
+    #[test]
+    fn finds_keyboard_flags() {
+        assert!(has_keyboard_flags("\x1b[?0u\x1b[?62c"));
+        assert!(has_keyboard_flags("\x1b[?31u\x1b[?62c"));
+        assert!(!has_keyboard_flags("\x1b[?62c"));
+        assert!(!has_keyboard_flags("\x1b[?1;2u\x1b[?62c"));
+    }
+
+    #[test]
+    fn finds_sixel_attribute() {
+        assert!(has_sixel_attribute("\x1b[?62;4;22c"));
+        assert!(!has_sixel_attribute("\x1b[?62;22c"));
+        // The first parameter is the conformance level, not an attribute.
+        assert!(!has_sixel_attribute("\x1b[?4;22c"));
+    }
+
+    #[test]
+    fn finds_iterm2_compatible_terminals() {
+        assert!(is_iterm2_compatible("\x1bP>|iTerm2 3.5.0\x1b\\\x1b[?62c"));
+        assert!(is_iterm2_compatible(
+            "\x1bP>|WezTerm 20240203\x1b\\\x1b[?62c"
+        ));
+        assert!(!is_iterm2_compatible("\x1bP>|kitty(0.35.2)\x1b\\\x1b[?62c"));
+        assert!(!is_iterm2_compatible("\x1b[?62c"));
+    }
+
+    #[test]
+    fn parses_background_color() {
+        assert_eq!(
+            parse_background_color("\x1b]11;rgb:ffff/8080/0000\x1b\\\x1b[?62c"),
+            Some((255, 128, 0))
+        );
+        assert_eq!(
+            parse_background_color("\x1b]11;rgb:f/80/000\x07"),
+            Some((255, 128, 0))
+        );
+        assert_eq!(parse_background_color("\x1b]11;rgb:ff/ff\x07"), None);
+        assert_eq!(parse_background_color("\x1b]11;rgb:fffff/0/0\x07"), None);
+        assert_eq!(parse_background_color("\x1b[?62c"), None);
+    }
+
+    #[test]
+    fn parses_mode_states() {
+        for state in ["1", "2", "3"] {
+            let response = format!("\x1b[?2026;{state}$y\x1b[?62c");
+            assert!(is_mode_supported(&response, 2026));
+        }
+        assert!(!is_mode_supported("\x1b[?2026;0$y\x1b[?62c", 2026));
+        assert!(!is_mode_supported("\x1b[?2026;4$y\x1b[?62c", 2026));
+        assert!(!is_mode_supported("\x1b[?1016;1$y\x1b[?62c", 2026));
+        assert!(!is_mode_supported("\x1b[?62c", 2026));
+    }
 }
@@ -0,0 +1,148 @@
+//! Decides when the engine may produce the next frame, so that frames are not
+//! produced faster than the terminal can draw them, e.g. over SSH.
+
+use flutter_sys::{current_time_nanos, Error, FlutterEngine, VsyncBaton};
+use std::time::Duration;
+
+/// How much a newly drawn frame contributes to the average time taken to draw
+/// a frame.
+const SMOOTHING: f64 = 0.2;
+
+pub(crate) struct FramePacer {
+    /// The shortest time between frames, from the maximum frame rate.
+    min_interval: Duration,
+    /// The average time taken by the terminal to draw a frame.
+    draw_duration: Duration,
+    /// When the last vsync was sent to the engine, on the clock of the engine.
+    last_vsync_nanos: u64,
+    /// The request from the engine which is waiting for the next vsync.
+    pending: Option<VsyncBaton>,
+}
+
+impl FramePacer {
+    pub(crate) fn new(max_fps: f64) -> Self {
+        Self {
+            min_interval: Duration::from_secs_f64(1.0 / max_fps),
+            draw_duration: Duration::ZERO,
+            last_vsync_nanos: 0,
+            pending: None,
+        }
+    }
+
+    /// Keeps the request for a vsync from the engine until the next frame is
+    /// due.
+    pub(crate) fn request_vsync(&mut self, baton: VsyncBaton) {
+        self.pending = Some(baton);
+    }
+
+    /// Updates the measured throughput of the terminal with the time taken to
+    /// draw a frame.
+    pub(crate) fn frame_drawn(&mut self, duration: Duration) {
+        self.draw_duration =
+            self.draw_duration.mul_f64(1.0 - SMOOTHING) + duration.mul_f64(SMOOTHING);
+    }
+
+    /// The time between frames, which is the slower of the maximum frame rate
+    /// and the rate the terminal can draw frames at.
+    fn interval(&self) -> Duration {
+        self.min_interval.max(self.draw_duration)
+    }
+
+    /// How long until the pending request for a vsync should be answered, if
+    /// there is one.
+    pub(crate) fn time_until_vsync(&self) -> Option<Duration> {
+        self.time_until_vsync_at(current_time_nanos())
+    }
+
+    fn time_until_vsync_at(&self, now_nanos: u64) -> Option<Duration> {
+        self.pending.as_ref()?;
+
+        let next_vsync_nanos = self.last_vsync_nanos + self.interval().as_nanos() as u64;
+        Some(Duration::from_nanos(
+            next_vsync_nanos.saturating_sub(now_nanos),
+        ))
+    }
+
+    /// Answers the pending request for a vsync if the next frame is due.
+    pub(crate) fn send_vsync_if_due(&mut self, engine: &FlutterEngine) -> Result<(), Error> {
+        match self.take_due_vsync(current_time_nanos()) {
+            Some((baton, start_nanos, target_nanos)) => {
+                engine.on_vsync(baton, start_nanos, target_nanos)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Takes the pending request for a vsync if the next frame is due at
+    /// `now_nanos`, with the start and target time of the frame.
+    fn take_due_vsync(&mut self, now_nanos: u64) -> Option<(VsyncBaton, u64, u64)> {
+        if self.time_until_vsync_at(now_nanos) != Some(Duration::ZERO) {
+            return None;
+        }
+        let baton = self.pending.take()?;
+
+        self.last_vsync_nanos = now_nanos;
+        Some((
+            baton,
+            now_nanos,
+            now_nanos + self.interval().as_nanos() as u64,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MILLIS: u64 = 1_000_000;
+
+    #[test]
+    fn interval_is_the_slower_of_the_max_fps_and_the_terminal() {
+        let mut frame_pacer = FramePacer::new(100.0);
+        assert_eq!(frame_pacer.interval(), Duration::from_millis(10));
+
+        frame_pacer.draw_duration = Duration::from_millis(5);
+        assert_eq!(frame_pacer.interval(), Duration::from_millis(10));
+
+        frame_pacer.draw_duration = Duration::from_millis(25);
+        assert_eq!(frame_pacer.interval(), Duration::from_millis(25));
+    }
+
+    #[test]
+    fn averages_draw_durations() {
+        let mut frame_pacer = FramePacer::new(60.0);
+        frame_pacer.frame_drawn(Duration::from_millis(100));
+        assert_eq!(frame_pacer.draw_duration, Duration::from_millis(20));
+
+        frame_pacer.frame_drawn(Duration::from_millis(100));
+        assert_eq!(frame_pacer.draw_duration, Duration::from_millis(36));
+
+        frame_pacer.frame_drawn(Duration::ZERO);
+        assert_eq!(frame_pacer.draw_duration.as_micros(), 28_800);
+    }
+
+    #[test]
+    fn does_not_send_vsync_without_a_request() {
+        let mut frame_pacer = FramePacer::new(100.0);
+        assert_eq!(frame_pacer.time_until_vsync_at(100 * MILLIS), None);
+        assert!(frame_pacer.take_due_vsync(100 * MILLIS).is_none());
+    }
+
+    #[test]
+    fn sends_vsync_when_the_next_frame_is_due() {
+        let mut frame_pacer = FramePacer::new(100.0);
+        frame_pacer.request_vsync(VsyncBaton::new(1));
+
+        let (_, start_nanos, target_nanos) = frame_pacer.take_due_vsync(100 * MILLIS).unwrap();
+        assert_eq!((start_nanos, target_nanos), (100 * MILLIS, 110 * MILLIS));
+        assert!(frame_pacer.take_due_vsync(100 * MILLIS).is_none());
+
+        frame_pacer.request_vsync(VsyncBaton::new(2));
+        assert_eq!(
+            frame_pacer.time_until_vsync_at(104 * MILLIS),
+            Some(Duration::from_millis(6))
+        );
+        assert!(frame_pacer.take_due_vsync(104 * MILLIS).is_none());
+        assert!(frame_pacer.take_due_vsync(110 * MILLIS).is_some());
+    }
+}
@@ -0,0 +1,126 @@
+//! Draws frames with the iTerm2 inline images protocol.
+//!
+//! See https://iterm2.com/documentation-images.html.
+
+use crate::color::{composite, Rgb};
+use crate::renderer::{Frame, Renderer};
+use base64::prelude::*;
+use crossterm::cursor::MoveTo;
+use crossterm::style::Print;
+use crossterm::QueueableCommand;
+use std::io::Write;
+
+pub(crate) struct Iterm2Renderer;
+
+impl Renderer for Iterm2Renderer {
+    fn draws_pixels(&self) -> bool {
+        true
+    }
+
+    fn present(&mut self, out: &mut dyn Write, frame: &Frame) -> std::io::Result<()> {
+        if frame.buffer.is_empty() {
+            return Ok(());
+        }
+
+        // The image is drawn from the cursor.
+        out.queue(MoveTo(0, 0))?;
+        out.queue(Print(encode(
+            frame.buffer,
+            frame.width,
+            frame.height,
+            frame.background,
+        )?))?;
+        out.flush()
+    }
+}
+
+/// Encodes `buffer`, which is a frame from the engine, as a PNG over
+/// `background` in an inline image escape sequence.
+fn encode(buffer: &[u8], width: usize, height: usize, background: Rgb) -> std::io::Result<String> {
+    let png = encode_png(buffer, width, height, background)?;
+
+    // \x1b]1337;File=...:{base64}\x07 : Display an image.
+    // inline=1                         : Display it instead of downloading it.
+    // size={bytes}                     : Size of the file before base64.
+    // width={w}px,height={h}px         : Draw it at the size of the frame.
+    // preserveAspectRatio=0            : Don't letterbox the image.
+    // doNotMoveCursor=1                : Don't scroll the screen after drawing
+    //                                    the image, which is supported by
+    //                                    WezTerm and iTerm2 3.5.
+    Ok(format!(
+        "\x1b]1337;File=inline=1;size={};width={}px;height={}px;preserveAspectRatio=0;doNotMoveCursor=1:{}\x07",
+        png.len(),
+        width,
+        height,
+        BASE64_STANDARD.encode(&png)
+    ))
+}
+
+fn encode_png(
+    buffer: &[u8],
+    width: usize,
+    height: usize,
+    background: Rgb,
+) -> std::io::Result<Vec<u8>> {
+    // PNGs aren't premultiplied, so composite instead of keeping the alpha.
+    let rgba: Vec<u8> = buffer
+        .chunks_exact(4)
+        .flat_map(|pixel| {
+            let (r, g, b) = composite(pixel, background);
+            [r, g, b, 255]
+        })
+        .collect();
+
+    let mut png = vec![];
+    let mut encoder = png::Encoder::new(&mut png, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    // Frames are encoded continuously, so favour speed over size.
+    encoder.set_compression(png::Compression::Fast);
+
+    let mut writer = encoder.write_header().map_err(std::io::Error::other)?;
+    writer
+        .write_image_data(&rgba)
+        .map_err(std::io::Error::other)?;
+    writer.finish().map_err(std::io::Error::other)?;
+
+    Ok(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::rgba_to_engine;
+
+    #[test]
+    fn presents_png_over_background() {
+        let buffer: Vec<u8> = [[0, 0, 0, 0], [255, 0, 0, 255]]
+            .into_iter()
+            .flat_map(rgba_to_engine)
+            .collect();
+        let frame = Frame {
+            buffer: &buffer,
+            width: 2,
+            height: 1,
+            offset: (0, 0),
+            background: (0, 0, 255),
+        };
+
+        let mut out = vec![];
+        Iterm2Renderer.present(&mut out, &frame).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        let (arguments, payload) = out
+            .strip_prefix("\x1b[1;1H\x1b]1337;File=")
+            .and_then(|out| out.strip_suffix('\x07'))
+            .and_then(|out| out.split_once(':'))
+            .unwrap();
+        assert!(arguments.contains(";width=2px;height=1px;"));
+
+        let png = BASE64_STANDARD.decode(payload).unwrap();
+        let mut reader = png::Decoder::new(png.as_slice()).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(pixels, [0, 0, 255, 255, 255, 0, 0, 255]);
+    }
+}
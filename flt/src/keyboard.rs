@@ -0,0 +1,492 @@
+//! Mapping from terminal key events to the key data expected by the Flutter
+//! framework.
+//!
+//! Physical keys are USB HID usage codes, and logical keys are the values of
+//! `LogicalKeyboardKey` in the framework. See
+//! https://github.com/flutter/flutter/blob/master/dev/tools/gen_keycodes/data/logical_key_data.g.json.
+//!
+//! Terminals don't report the physical key that was pressed, so physical keys
+//! are derived assuming an US keyboard layout.
+
+use crossterm::event::{KeyCode, KeyEventKind, KeyModifiers, ModifierKeyCode};
+use flutter_sys::FlutterKeyEventType;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+/// The channel for raw key messages. See `SystemChannels.keyEvent`.
+pub(crate) const KEY_EVENT_CHANNEL: &str = "flutter/keyevent";
+
+/// USB HID usage page for keyboards.
+const USB_HID_KEYBOARD_PAGE: u64 = 0x00070000;
+
+/// Plane of logical keys which don't produce a printable character.
+const UNPRINTABLE_PLANE: u64 = 0x00100000000;
+
+/// Plane of logical keys defined by Flutter, which contains the left / right
+/// variants of modifier keys.
+const FLUTTER_PLANE: u64 = 0x00200000000;
+
+/// Plane of physical keys for characters that can't be found on an US
+/// keyboard layout.
+const UNKNOWN_PHYSICAL_PLANE: u64 = 0x01100000000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct FlutterKey {
+    pub(crate) physical: u64,
+    pub(crate) logical: u64,
+    /// The text produced by the key, if any.
+    pub(crate) character: Option<String>,
+    /// The W3C `KeyboardEvent.code` of the key, used for raw key messages.
+    code: String,
+    /// The W3C `KeyboardEvent.key` of the key, used for raw key messages.
+    key: String,
+}
+
+impl FlutterKey {
+    fn named(usage: u64, logical: u64, code: &str, key: &str) -> Self {
+        Self {
+            physical: USB_HID_KEYBOARD_PAGE | usage,
+            logical,
+            character: None,
+            code: code.to_string(),
+            key: key.to_string(),
+        }
+    }
+
+    /// The raw key message which has to be sent on [KEY_EVENT_CHANNEL] after
+    /// the key data for the framework to dispatch the key event.
+    ///
+    /// The `web` keymap is used as it is the only one which isn't tied to the
+    /// key codes of a particular platform.
+    pub(crate) fn raw_key_message(&self, is_down: bool, modifiers: KeyModifiers) -> Vec<u8> {
+        json!({
+            "type": if is_down { "keydown" } else { "keyup" },
+            "keymap": "web",
+            "code": self.code,
+            "key": self.key,
+            "metaState": meta_state(modifiers),
+        })
+        .to_string()
+        .into_bytes()
+    }
+}
+
+/// Converts a terminal key to a Flutter key, or `None` if there isn't an
+/// equivalent.
+pub(crate) fn to_flutter_key(code: KeyCode) -> Option<FlutterKey> {
+    let key = match code {
+        KeyCode::Char(c) => return Some(char_key(c)),
+        KeyCode::Backspace => {
+            FlutterKey::named(0x2a, UNPRINTABLE_PLANE | 0x08, "Backspace", "Backspace")
+        }
+        KeyCode::Tab | KeyCode::BackTab => {
+            FlutterKey::named(0x2b, UNPRINTABLE_PLANE | 0x09, "Tab", "Tab")
+        }
+        KeyCode::Enter => FlutterKey::named(0x28, UNPRINTABLE_PLANE | 0x0d, "Enter", "Enter"),
+        KeyCode::Esc => FlutterKey::named(0x29, UNPRINTABLE_PLANE | 0x1b, "Escape", "Escape"),
+        KeyCode::Delete => FlutterKey::named(0x4c, UNPRINTABLE_PLANE | 0x7f, "Delete", "Delete"),
+        KeyCode::Down => {
+            FlutterKey::named(0x51, UNPRINTABLE_PLANE | 0x301, "ArrowDown", "ArrowDown")
+        }
+        KeyCode::Left => {
+            FlutterKey::named(0x50, UNPRINTABLE_PLANE | 0x302, "ArrowLeft", "ArrowLeft")
+        }
+        KeyCode::Right => {
+            FlutterKey::named(0x4f, UNPRINTABLE_PLANE | 0x303, "ArrowRight", "ArrowRight")
+        }
+        KeyCode::Up => FlutterKey::named(0x52, UNPRINTABLE_PLANE | 0x304, "ArrowUp", "ArrowUp"),
+        KeyCode::End => FlutterKey::named(0x4d, UNPRINTABLE_PLANE | 0x305, "End", "End"),
+        KeyCode::Home => FlutterKey::named(0x4a, UNPRINTABLE_PLANE | 0x306, "Home", "Home"),
+        KeyCode::PageDown => {
+            FlutterKey::named(0x4e, UNPRINTABLE_PLANE | 0x307, "PageDown", "PageDown")
+        }
+        KeyCode::PageUp => FlutterKey::named(0x4b, UNPRINTABLE_PLANE | 0x308, "PageUp", "PageUp"),
+        KeyCode::Insert => FlutterKey::named(0x49, UNPRINTABLE_PLANE | 0x407, "Insert", "Insert"),
+        KeyCode::CapsLock => {
+            FlutterKey::named(0x39, UNPRINTABLE_PLANE | 0x104, "CapsLock", "CapsLock")
+        }
+        KeyCode::NumLock => {
+            FlutterKey::named(0x53, UNPRINTABLE_PLANE | 0x10a, "NumLock", "NumLock")
+        }
+        KeyCode::ScrollLock => {
+            FlutterKey::named(0x47, UNPRINTABLE_PLANE | 0x10c, "ScrollLock", "ScrollLock")
+        }
+        KeyCode::Menu => FlutterKey::named(
+            0x65,
+            UNPRINTABLE_PLANE | 0x505,
+            "ContextMenu",
+            "ContextMenu",
+        ),
+        KeyCode::Pause => FlutterKey::named(0x48, UNPRINTABLE_PLANE | 0x509, "Pause", "Pause"),
+        KeyCode::PrintScreen => FlutterKey::named(
+            0x46,
+            UNPRINTABLE_PLANE | 0x608,
+            "PrintScreen",
+            "PrintScreen",
+        ),
+        KeyCode::F(n @ 1..=12) => {
+            let name = format!("F{n}");
+            FlutterKey::named(
+                0x3a + n as u64 - 1,
+                UNPRINTABLE_PLANE | (0x800 + n as u64),
+                &name,
+                &name,
+            )
+        }
+        KeyCode::F(n @ 13..=24) => {
+            let name = format!("F{n}");
+            FlutterKey::named(
+                0x68 + n as u64 - 13,
+                UNPRINTABLE_PLANE | (0x800 + n as u64),
+                &name,
+                &name,
+            )
+        }
+        KeyCode::Modifier(modifier) => return modifier_key(modifier),
+        _ => return None,
+    };
+    Some(key)
+}
+
+/// The modifier keys which are held down according to `modifiers`.
+///
+/// Without the kitty keyboard protocol, terminals only report modifiers as
+/// part of another key, so these are used to synthesize presses of the
+/// modifier keys around that key.
+pub(crate) fn modifier_keys(modifiers: KeyModifiers) -> Vec<FlutterKey> {
+    [
+        (KeyModifiers::CONTROL, ModifierKeyCode::LeftControl),
+        (KeyModifiers::SHIFT, ModifierKeyCode::LeftShift),
+        (KeyModifiers::ALT, ModifierKeyCode::LeftAlt),
+        (
+            KeyModifiers::SUPER | KeyModifiers::META,
+            ModifierKeyCode::LeftSuper,
+        ),
+    ]
+    .into_iter()
+    .filter(|(modifier, _)| modifiers.intersects(*modifier))
+    .filter_map(|(_, key)| modifier_key(key))
+    .collect()
+}
+
+fn modifier_key(modifier: ModifierKeyCode) -> Option<FlutterKey> {
+    let key = match modifier {
+        ModifierKeyCode::LeftControl => {
+            FlutterKey::named(0xe0, FLUTTER_PLANE | 0x100, "ControlLeft", "Control")
+        }
+        ModifierKeyCode::RightControl => {
+            FlutterKey::named(0xe4, FLUTTER_PLANE | 0x101, "ControlRight", "Control")
+        }
+        ModifierKeyCode::LeftShift => {
+            FlutterKey::named(0xe1, FLUTTER_PLANE | 0x102, "ShiftLeft", "Shift")
+        }
+        ModifierKeyCode::RightShift => {
+            FlutterKey::named(0xe5, FLUTTER_PLANE | 0x103, "ShiftRight", "Shift")
+        }
+        ModifierKeyCode::LeftAlt => {
+            FlutterKey::named(0xe2, FLUTTER_PLANE | 0x104, "AltLeft", "Alt")
+        }
+        ModifierKeyCode::RightAlt => {
+            FlutterKey::named(0xe6, FLUTTER_PLANE | 0x105, "AltRight", "Alt")
+        }
+        ModifierKeyCode::LeftSuper | ModifierKeyCode::LeftMeta => {
+            FlutterKey::named(0xe3, FLUTTER_PLANE | 0x106, "MetaLeft", "Meta")
+        }
+        ModifierKeyCode::RightSuper | ModifierKeyCode::RightMeta => {
+            FlutterKey::named(0xe7, FLUTTER_PLANE | 0x107, "MetaRight", "Meta")
+        }
+        _ => return None,
+    };
+    Some(key)
+}
+
+/// Tracks the keys which the framework considers to be pressed.
+///
+/// The framework asserts that a key is only released after it is pressed, and
+/// that it isn't pressed again before it is released, which terminals don't
+/// guarantee.
+#[derive(Default)]
+pub(crate) struct KeyboardState {
+    /// The pressed keys, keyed by their physical key.
+    pressed: HashMap<u64, FlutterKey>,
+}
+
+impl KeyboardState {
+    /// Records a terminal key event for `key`, returning the event which
+    /// should be sent to the framework, or `None` if it shouldn't be sent.
+    ///
+    /// The logical key of `key` is updated to match the one it was pressed
+    /// with, as it can change when modifiers change.
+    pub(crate) fn update(
+        &mut self,
+        key: &mut FlutterKey,
+        kind: KeyEventKind,
+    ) -> Option<FlutterKeyEventType> {
+        match (kind, self.pressed.get(&key.physical)) {
+            (KeyEventKind::Press | KeyEventKind::Repeat, Some(pressed)) => {
+                key.logical = pressed.logical;
+                Some(FlutterKeyEventType::Repeat)
+            }
+            (KeyEventKind::Press, None) => {
+                self.pressed.insert(key.physical, key.clone());
+                Some(FlutterKeyEventType::Down)
+            }
+            (KeyEventKind::Repeat, None) => None,
+            (KeyEventKind::Release, _) => {
+                key.logical = self.pressed.remove(&key.physical)?.logical;
+                Some(FlutterKeyEventType::Up)
+            }
+        }
+    }
+
+    /// Forgets the pressed keys, returning them so that their releases can be
+    /// sent to the framework.
+    pub(crate) fn release_all(&mut self) -> Vec<FlutterKey> {
+        self.pressed.drain().map(|(_, key)| key).collect()
+    }
+}
+
+/// Whether the framework handled the key event, given the response to a raw
+/// key message.
+pub(crate) fn is_handled(response: &[u8]) -> bool {
+    match serde_json::from_slice(response) {
+        Ok(Value::Object(map)) => map.get("handled").and_then(Value::as_bool).unwrap_or(false),
+        _ => false,
+    }
+}
+
+fn char_key(c: char) -> FlutterKey {
+    // The logical key of a character ignores shift, so `A` and `a` are both
+    // `LogicalKeyboardKey.keyA`, and `!` is `LogicalKeyboardKey.digit1`.
+    let (usage, base, code) = match c {
+        'a'..='z' | 'A'..='Z' => {
+            let lower = c.to_ascii_lowercase();
+            let code = format!("Key{}", lower.to_ascii_uppercase());
+            (Some(0x04 + (lower as u64 - 'a' as u64)), lower, code)
+        }
+        '0'..='9' => (Some(digit_usage(c)), c, format!("Digit{c}")),
+        _ if SHIFTED_DIGITS.contains(&c) => {
+            let digit = SHIFTED_DIGITS
+                .iter()
+                .position(|shifted| *shifted == c)
+                .unwrap();
+            let digit = char::from_digit(digit as u32, 10).unwrap();
+            (Some(digit_usage(digit)), digit, format!("Digit{digit}"))
+        }
+        _ => match SYMBOLS
+            .iter()
+            .find(|(base, shifted, _, _)| *base == c || *shifted == c)
+        {
+            Some((base, _, usage, code)) => (Some(*usage), *base, code.to_string()),
+            None => (None, c, String::new()),
+        },
+    };
+
+    let logical = base as u64;
+
+    FlutterKey {
+        physical: usage.map_or(UNKNOWN_PHYSICAL_PLANE | logical, |usage| {
+            USB_HID_KEYBOARD_PAGE | usage
+        }),
+        logical,
+        character: Some(c.to_string()),
+        code,
+        key: c.to_string(),
+    }
+}
+
+fn digit_usage(digit: char) -> u64 {
+    match digit {
+        '0' => 0x27,
+        _ => 0x1e + (digit as u64 - '1' as u64),
+    }
+}
+
+/// Symbols on an US keyboard layout, as (unshifted, shifted, usage, code).
+const SYMBOLS: [(char, char, u64, &str); 12] = [
+    (' ', ' ', 0x2c, "Space"),
+    ('-', '_', 0x2d, "Minus"),
+    ('=', '+', 0x2e, "Equal"),
+    ('[', '{', 0x2f, "BracketLeft"),
+    (']', '}', 0x30, "BracketRight"),
+    ('\\', '|', 0x31, "Backslash"),
+    (';', ':', 0x33, "Semicolon"),
+    ('\'', '"', 0x34, "Quote"),
+    ('`', '~', 0x35, "Backquote"),
+    (',', '<', 0x36, "Comma"),
+    ('.', '>', 0x37, "Period"),
+    ('/', '?', 0x38, "Slash"),
+];
+
+/// Shifted digits on an US keyboard layout, indexed by the digit.
+const SHIFTED_DIGITS: [char; 10] = [')', '!', '@', '#', '$', '%', '^', '&', '*', '('];
+
+/// The `metaState` bitmask of `RawKeyEventDataWeb`.
+fn meta_state(modifiers: KeyModifiers) -> u64 {
+    let mut result = 0;
+    if modifiers.contains(KeyModifiers::SHIFT) {
+        result |= 0x01;
+    }
+    if modifiers.contains(KeyModifiers::ALT) {
+        result |= 0x02;
+    }
+    if modifiers.contains(KeyModifiers::CONTROL) {
+        result |= 0x04;
+    }
+    if modifiers.contains(KeyModifiers::SUPER) || modifiers.contains(KeyModifiers::META) {
+        result |= 0x08;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_named_keys() {
+        let key = to_flutter_key(KeyCode::Enter).unwrap();
+        assert_eq!(key.physical, 0x00070028);
+        assert_eq!(key.logical, 0x0010000000d);
+        assert_eq!(key.character, None);
+
+        let key = to_flutter_key(KeyCode::F(1)).unwrap();
+        assert_eq!((key.physical, key.logical), (0x0007003a, 0x00100000801));
+        let key = to_flutter_key(KeyCode::F(12)).unwrap();
+        assert_eq!((key.physical, key.logical), (0x00070045, 0x0010000080c));
+        let key = to_flutter_key(KeyCode::F(13)).unwrap();
+        assert_eq!((key.physical, key.logical), (0x00070068, 0x0010000080d));
+
+        let key = to_flutter_key(KeyCode::Modifier(ModifierKeyCode::RightShift)).unwrap();
+        assert_eq!((key.physical, key.logical), (0x000700e5, 0x00200000103));
+
+        assert_eq!(to_flutter_key(KeyCode::Null), None);
+        assert_eq!(to_flutter_key(KeyCode::F(25)), None);
+    }
+
+    #[test]
+    fn maps_letters_to_the_lowercase_logical_key() {
+        let lower = char_key('a');
+        let upper = char_key('A');
+        assert_eq!((lower.physical, lower.logical), (0x00070004, 'a' as u64));
+        assert_eq!(
+            (upper.physical, upper.logical),
+            (lower.physical, lower.logical)
+        );
+        assert_eq!(upper.character.as_deref(), Some("A"));
+        assert_eq!(upper.code, "KeyA");
+    }
+
+    #[test]
+    fn maps_shifted_digits_and_symbols_to_the_unshifted_logical_key() {
+        let digit = char_key('1');
+        let shifted = char_key('!');
+        assert_eq!(
+            (shifted.physical, shifted.logical),
+            (digit.physical, '1' as u64)
+        );
+        assert_eq!(shifted.character.as_deref(), Some("!"));
+        assert_eq!(shifted.code, "Digit1");
+
+        let shifted = char_key(')');
+        assert_eq!(
+            (shifted.physical, shifted.logical),
+            (0x00070027, '0' as u64)
+        );
+
+        let symbol = char_key('-');
+        let shifted = char_key('_');
+        assert_eq!(
+            (shifted.physical, shifted.logical),
+            (symbol.physical, '-' as u64)
+        );
+        assert_eq!(shifted.code, "Minus");
+    }
+
+    #[test]
+    fn maps_other_characters_to_unknown_physical_keys() {
+        let key = char_key('é');
+        assert_eq!(key.logical, 'é' as u64);
+        assert_eq!(key.physical, UNKNOWN_PHYSICAL_PLANE | 'é' as u64);
+        assert_eq!(key.code, "");
+    }
+
+    #[test]
+    fn maps_digits_to_usages() {
+        assert_eq!(digit_usage('1'), 0x1e);
+        assert_eq!(digit_usage('9'), 0x26);
+        assert_eq!(digit_usage('0'), 0x27);
+    }
+
+    #[test]
+    fn encodes_modifiers_as_meta_state() {
+        assert_eq!(meta_state(KeyModifiers::NONE), 0);
+        assert_eq!(
+            meta_state(KeyModifiers::SHIFT | KeyModifiers::CONTROL),
+            0x05
+        );
+        assert_eq!(meta_state(KeyModifiers::ALT), 0x02);
+        assert_eq!(meta_state(KeyModifiers::SUPER), 0x08);
+        assert_eq!(meta_state(KeyModifiers::META), 0x08);
+    }
+
+    #[test]
+    fn tracks_presses_repeats_and_releases() {
+        let mut keyboard = KeyboardState::default();
+        let mut key = char_key('a');
+
+        assert_eq!(
+            keyboard.update(&mut key, KeyEventKind::Press),
+            Some(FlutterKeyEventType::Down)
+        );
+        assert_eq!(
+            keyboard.update(&mut key, KeyEventKind::Repeat),
+            Some(FlutterKeyEventType::Repeat)
+        );
+        // Pressed again without a release, e.g. without the kitty keyboard
+        // protocol.
+        assert_eq!(
+            keyboard.update(&mut key, KeyEventKind::Press),
+            Some(FlutterKeyEventType::Repeat)
+        );
+
+        // Released as another logical key, which the framework would reject.
+        let mut released = char_key('a');
+        released.logical = 0;
+        assert_eq!(
+            keyboard.update(&mut released, KeyEventKind::Release),
+            Some(FlutterKeyEventType::Up)
+        );
+        assert_eq!(released.logical, 'a' as u64);
+        assert!(keyboard.pressed.is_empty());
+    }
+
+    #[test]
+    fn drops_releases_and_repeats_of_keys_which_are_not_pressed() {
+        let mut keyboard = KeyboardState::default();
+        let mut key = char_key('a');
+        assert_eq!(keyboard.update(&mut key, KeyEventKind::Release), None);
+        assert_eq!(keyboard.update(&mut key, KeyEventKind::Repeat), None);
+        assert!(keyboard.pressed.is_empty());
+    }
+
+    #[test]
+    fn releases_all_keys() {
+        let mut keyboard = KeyboardState::default();
+        let mut shift = to_flutter_key(KeyCode::Modifier(ModifierKeyCode::LeftShift)).unwrap();
+        let mut key = char_key('a');
+        keyboard.update(&mut shift, KeyEventKind::Press);
+        keyboard.update(&mut key, KeyEventKind::Press);
+
+        let mut released = keyboard.release_all();
+        released.sort_by_key(|key| key.physical);
+        assert_eq!(released, [key.clone(), shift]);
+
+        // Presses after that are new presses, and releases are dropped.
+        assert_eq!(keyboard.update(&mut key, KeyEventKind::Release), None);
+        assert_eq!(
+            keyboard.update(&mut key, KeyEventKind::Press),
+            Some(FlutterKeyEventType::Down)
+        );
+    }
+}
@@ -0,0 +1,412 @@
+//! Draws frames with the kitty graphics protocol.
+//!
+//! See https://sw.kovidgoyal.net/kitty/graphics-protocol/.
+
+use crate::color::composite_frame;
+use crate::damage::{crop, damaged_rects};
+use crate::renderer::{Frame, Renderer};
+use crate::shared_memory::{SharedMemoryBuffer, SharedMemoryRing};
+use base64::prelude::*;
+use crossterm::cursor::MoveTo;
+use crossterm::style::Print;
+use crossterm::QueueableCommand;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use std::io::{Stdout, Write};
+
+pub(crate) struct KittyRenderer {
+    support: KittySupport,
+    shm_ring: Option<SharedMemoryRing>,
+    shm_buffer: Option<SharedMemoryBuffer>,
+    frame_count: u64,
+    /// Whether only the changed areas of frames are sent. This is only worth
+    /// it with [KittyTransmission::Direct], and needs the terminal to support
+    /// editing the frames of images.
+    damage_updates: bool,
+    /// The last frame which was sent, to find what changed in the next one.
+    /// This is empty when the terminal needs the full frame.
+    previous: Vec<u8>,
+    previous_size: (usize, usize),
+}
+
+/// What the terminal supports for the kitty graphics protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct KittySupport {
+    pub(crate) transmission: KittyTransmission,
+    /// Whether the terminal updates the access time of the files of
+    /// [KittyTransmission::SharedMemoryRing] when reading them, see
+    /// [SharedMemoryRing].
+    pub(crate) ring_handshake: bool,
+    /// Whether the terminal supports editing the frames of images.
+    pub(crate) frame_editing: bool,
+}
+
+/// How frames are transmitted with the kitty graphics protocol, from the
+/// fastest to the slowest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum KittyTransmission {
+    /// `t=f`: Through files in shared memory, which are reused across frames.
+    SharedMemoryRing,
+    /// `t=s`: Through POSIX shared memory, with a new object for every frame
+    /// as the terminal unlinks it after reading it.
+    SharedMemory,
+    /// `t=t`: Through a temporary file, which the terminal deletes after
+    /// reading it.
+    TempFile,
+    /// `t=d`: In the escape codes, in chunks and compressed. This is the only
+    /// option when the terminal is on another machine.
+    Direct,
+}
+
+/// Max size of the base64 payload of each escape code with
+/// [KittyTransmission::Direct].
+const KITTY_CHUNK_SIZE: usize = 4096;
+
+/// Frames where more than `1 / MAX_DAMAGE_FRACTION` of the pixels changed are
+/// sent in full, as sending the changed areas separately isn't worth it.
+const MAX_DAMAGE_FRACTION: usize = 2;
+
+impl KittyRenderer {
+    pub(crate) fn new(support: KittySupport) -> Self {
+        Self {
+            support,
+            shm_ring: None,
+            shm_buffer: None,
+            frame_count: 0,
+            damage_updates: support.transmission == KittyTransmission::Direct
+                && support.frame_editing,
+            previous: vec![],
+            previous_size: (0, 0),
+        }
+    }
+}
+
+impl Renderer for KittyRenderer {
+    fn draws_pixels(&self) -> bool {
+        true
+    }
+
+    fn present(&mut self, out: &mut dyn Write, frame: &Frame) -> std::io::Result<()> {
+        if frame.buffer.is_empty() {
+            return Ok(());
+        }
+
+        // Generate unique SHM segment / file for this frame
+        self.frame_count += 1;
+
+        // The terminal blends transparent pixels with its own background
+        // color, but treats them as not premultiplied.
+        let buffer = composite_frame(frame.buffer, frame.background);
+
+        let size = (frame.width, frame.height);
+        let can_diff =
+            self.damage_updates && !self.previous.is_empty() && self.previous_size == size;
+        self.previous_size = size;
+
+        if can_diff {
+            let rects = damaged_rects(&self.previous, &buffer, frame.width, frame.height);
+            if rects.is_empty() {
+                return Ok(());
+            }
+
+            let damaged_area: usize = rects.iter().map(|rect| rect.area()).sum();
+            if damaged_area * MAX_DAMAGE_FRACTION <= buffer.len() / 4 {
+                for rect in &rects {
+                    // Edit the displayed image in place.
+                    // a=f: transmit data for an animation frame
+                    // r=1: edit the first (root) frame instead of adding one
+                    // x={x},y={y}: where the data goes in the frame
+                    // s={width},v={height}: dimensions of the data
+                    // X=1: replace the pixels instead of blending them
+                    let control = format!(
+                        "f=32,s={},v={},a=f,q=2,i=1,r=1,x={},y={},X=1",
+                        rect.width, rect.height, rect.x, rect.y
+                    );
+                    write_direct(out, &control, &crop(&buffer, frame.width, *rect))?;
+                }
+                out.flush()?;
+
+                self.previous = buffer;
+                return Ok(());
+            }
+        }
+
+        // Send Command
+        out.queue(MoveTo(0, 0))?;
+
+        // Initialize transfer.
+        // f=32: 32-bit RGBA
+        // s={pixel_width},v={pixel_height}: dimensions
+        // a=T: transmit and display
+        // q=2: quiet mode (no response)
+        // i=1: image ID for overwriting
+        // p=1 (Constant Placement ID to avoid leaking placements)
+        // z=1: z-index (positive = above text)
+        // C=1: do not move cursor
+        let control = format!(
+            "f=32,s={},v={},a=T,q=2,i=1,p=1,z=1,C=1",
+            frame.width, frame.height
+        );
+
+        match self.support.transmission {
+            KittyTransmission::SharedMemoryRing => {
+                let ring = match &mut self.shm_ring {
+                    Some(ring) => ring,
+                    None => self.shm_ring.insert(SharedMemoryRing::new(
+                        buffer.len(),
+                        self.support.ring_handshake,
+                    )?),
+                };
+                let path = ring.write(&buffer)?;
+
+                // t=f (File), which the terminal doesn't delete.
+                // Payload is the encoded path of the file.
+                out.queue(Print(format!(
+                    "\x1b_G{},t=f;{}\x1b\\",
+                    control,
+                    BASE64_STANDARD.encode(path.to_string_lossy().as_bytes())
+                )))?;
+                out.flush()?;
+            }
+            KittyTransmission::SharedMemory => {
+                let mut new_shm = SharedMemoryBuffer::new(buffer.len(), self.frame_count)?;
+
+                // Write to SHM
+                if let Some(map) = &mut new_shm.map {
+                    map[0..buffer.len()].copy_from_slice(&buffer);
+                    let _ = map.flush();
+                }
+
+                // t=s (Shared Memory)
+                // Payload is the encoded name of the NEW SHM segment
+                out.queue(Print(format!(
+                    "\x1b_G{},t=s;{}\x1b\\",
+                    control,
+                    BASE64_STANDARD.encode(&new_shm.name)
+                )))?;
+                out.flush()?;
+
+                // Store the new SHM buffer.
+                // This drops the previous one (if any), which triggers shm_unlink.
+                self.shm_buffer = Some(new_shm);
+            }
+            KittyTransmission::TempFile => {
+                let path = kitty_temp_file_path(self.frame_count);
+                std::fs::write(&path, &buffer)?;
+
+                // t=t (Temporary File), which the terminal deletes.
+                // Payload is the encoded path of the file.
+                out.queue(Print(format!(
+                    "\x1b_G{},t=t;{}\x1b\\",
+                    control,
+                    BASE64_STANDARD.encode(path.to_string_lossy().as_bytes())
+                )))?;
+                out.flush()?;
+            }
+            KittyTransmission::Direct => {
+                write_direct(out, &control, &buffer)?;
+                out.flush()?;
+            }
+        }
+
+        if self.damage_updates {
+            self.previous = buffer;
+        }
+        Ok(())
+    }
+
+    fn clear(&mut self) {
+        self.previous.clear();
+    }
+
+    fn teardown(&mut self, out: &mut dyn Write) -> std::io::Result<()> {
+        // On iTerm2, kitty graphics need to be explicitly cleared, otherwise the last frame will persist on the screen.
+        // \x1b_G: Start of Kitty graphics command
+        // a=d: Action = delete
+        // d=a: Delete = all
+        // \x1b\\: String terminator
+        out.queue(Print("\x1b_Ga=d,d=a\x1b\\"))?;
+        out.flush()
+    }
+}
+
+/// Writes a command with `control` data to transmit `data` in the escape
+/// codes, compressed and in chunks.
+fn write_direct(out: &mut dyn Write, control: &str, data: &[u8]) -> std::io::Result<()> {
+    let mut encoder = ZlibEncoder::new(vec![], Compression::fast());
+    encoder.write_all(data)?;
+    let payload = BASE64_STANDARD.encode(encoder.finish()?);
+
+    // t=d (Direct), o=z (zlib compressed)
+    // The payload is split into chunks, where m=1 means more chunks
+    // follow. Only the first chunk has the control data.
+    let chunks = payload.as_bytes().chunks(KITTY_CHUNK_SIZE);
+    let chunk_count = chunks.len();
+    for (i, chunk) in chunks.enumerate() {
+        let more = (i + 1 < chunk_count) as u8;
+        let chunk = std::str::from_utf8(chunk).unwrap();
+        if i == 0 {
+            out.queue(Print(format!(
+                "\x1b_G{},t=d,o=z,m={};{}\x1b\\",
+                control, more, chunk
+            )))?;
+        } else {
+            out.queue(Print(format!("\x1b_Gm={};{}\x1b\\", more, chunk)))?;
+        }
+    }
+    Ok(())
+}
+
+impl KittySupport {
+    /// Queries the terminal for what it supports.
+    ///
+    /// This must be called in raw mode.
+    pub(crate) fn detect(stdout: &mut Stdout) -> Self {
+        let (transmission, ring_handshake) = detect_kitty_transmission(stdout);
+        // Only needed with direct transmission.
+        let frame_editing = transmission == KittyTransmission::Direct
+            && crate::feature::kitty_frame_editing_supported(stdout);
+        Self {
+            transmission,
+            ring_handshake,
+            frame_editing,
+        }
+    }
+}
+
+/// Finds the fastest way to transmit frames with the kitty graphics protocol
+/// which the terminal supports, along with whether the handshake of
+/// [KittyTransmission::SharedMemoryRing] works.
+fn detect_kitty_transmission(stdout: &mut Stdout) -> (KittyTransmission, bool) {
+    // Don't bother when the terminal is surely on another machine.
+    if std::env::var_os("SSH_CONNECTION").is_some() {
+        return (KittyTransmission::Direct, false);
+    }
+
+    // A 1x1 image to test reading.
+    let pixel = [0u8; 4];
+
+    if let Ok(mut ring) = SharedMemoryRing::new(pixel.len(), true) {
+        if let Ok(path) = ring.write(&pixel) {
+            let payload = BASE64_STANDARD.encode(path.to_string_lossy().as_bytes());
+            if crate::feature::kitty_transmission_supported(stdout, 'f', &payload) {
+                // Without the handshake, slots are reused without waiting for
+                // the terminal, which is still better than the alternatives.
+                return (KittyTransmission::SharedMemoryRing, ring.verify_handshake());
+            }
+        }
+    }
+
+    // Suffix 0 is never used by frames.
+    if let Ok(mut shm) = SharedMemoryBuffer::new(pixel.len(), 0) {
+        if let Some(map) = &mut shm.map {
+            map.copy_from_slice(&pixel);
+        }
+        let payload = BASE64_STANDARD.encode(&shm.name);
+        if crate::feature::kitty_transmission_supported(stdout, 's', &payload) {
+            return (KittyTransmission::SharedMemory, false);
+        }
+    }
+
+    let path = kitty_temp_file_path(0);
+    if std::fs::write(&path, pixel).is_ok() {
+        let payload = BASE64_STANDARD.encode(path.to_string_lossy().as_bytes());
+        let supported = crate::feature::kitty_transmission_supported(stdout, 't', &payload);
+        // The terminal only deletes the file when it reads it.
+        let _ = std::fs::remove_file(&path);
+        if supported {
+            return (KittyTransmission::TempFile, false);
+        }
+    }
+
+    (KittyTransmission::Direct, false)
+}
+
+/// The path of the temporary file for a frame with
+/// [KittyTransmission::TempFile].
+fn kitty_temp_file_path(suffix: u64) -> std::path::PathBuf {
+    // Terminals only read temporary files which have this in their name.
+    std::env::temp_dir().join(format!(
+        "flt-tty-graphics-protocol-{}-{}",
+        std::process::id(),
+        suffix
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::read::ZlibDecoder;
+    use std::io::Read;
+
+    fn direct_renderer(frame_editing: bool) -> KittyRenderer {
+        KittyRenderer::new(KittySupport {
+            transmission: KittyTransmission::Direct,
+            ring_handshake: false,
+            frame_editing,
+        })
+    }
+
+    fn frame(buffer: &[u8]) -> Frame<'_> {
+        Frame {
+            buffer,
+            width: 64,
+            height: 64,
+            offset: (0, 0),
+            background: (0, 0, 0),
+        }
+    }
+
+    fn present(renderer: &mut KittyRenderer, buffer: &[u8]) -> String {
+        let mut out = vec![];
+        renderer.present(&mut out, &frame(buffer)).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn presents_frame_with_direct_transmission() {
+        let buffer = vec![255; 64 * 64 * 4];
+        let out = present(&mut direct_renderer(true), &buffer);
+
+        let (control, payload) = out
+            .strip_prefix("\x1b[1;1H\x1b_G")
+            .and_then(|out| out.strip_suffix("\x1b\\"))
+            .and_then(|out| out.split_once(';'))
+            .unwrap();
+        assert_eq!(
+            control,
+            "f=32,s=64,v=64,a=T,q=2,i=1,p=1,z=1,C=1,t=d,o=z,m=0"
+        );
+
+        let compressed = BASE64_STANDARD.decode(payload).unwrap();
+        let mut decoded = vec![];
+        ZlibDecoder::new(compressed.as_slice())
+            .read_to_end(&mut decoded)
+            .unwrap();
+        assert_eq!(decoded, buffer);
+    }
+
+    #[test]
+    fn presents_changed_areas() {
+        let mut renderer = direct_renderer(true);
+        let mut buffer = vec![255; 64 * 64 * 4];
+        present(&mut renderer, &buffer);
+
+        assert_eq!(present(&mut renderer, &buffer), "");
+
+        buffer[0] = 0;
+        let out = present(&mut renderer, &buffer);
+        assert!(out.starts_with("\x1b_Gf=32,s=32,v=32,a=f,q=2,i=1,r=1,x=0,y=0,X=1,t=d"));
+    }
+
+    #[test]
+    fn presents_full_frames_without_frame_editing() {
+        let mut renderer = direct_renderer(false);
+        let mut buffer = vec![255; 64 * 64 * 4];
+        present(&mut renderer, &buffer);
+
+        buffer[0] = 0;
+        let out = present(&mut renderer, &buffer);
+        assert!(out.starts_with("\x1b[1;1H\x1b_Gf=32,s=64,v=64,a=T,"));
+    }
+}
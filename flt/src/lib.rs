@@ -1,12 +1,31 @@
+mod ansi;
+mod color;
 mod constants;
+mod damage;
 mod embedder;
 mod error;
 mod event;
 mod feature;
+mod frame_pacer;
+mod iterm2;
+mod keyboard;
+mod kitty;
+mod lifecycle;
+mod mouse_cursor_plugin;
+mod platform_plugin;
+mod pointer;
+mod render_thread;
+mod renderer;
 mod semantics;
+mod shared_memory;
+mod signals;
+mod sixel;
 mod task_runner;
 mod terminal_event;
 mod terminal_window;
 
+pub use ansi::AnsiGlyphs;
+pub use color::{ColorDepth, Dithering};
 pub use embedder::*;
 pub use error::*;
+pub use renderer::RendererKind;
@@ -0,0 +1,54 @@
+//! Implements the platform side of the `flutter/lifecycle` channel.
+//!
+//! See https://api.flutter.dev/flutter/services/SystemChannels/lifecycle-constant.html.
+
+use flutter_sys::{Error, FlutterEngine};
+
+pub(crate) const CHANNEL: &str = "flutter/lifecycle";
+
+/// The equivalent of `AppLifecycleState` in the framework.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum AppLifecycleState {
+    /// The terminal is focused.
+    Resumed,
+    /// The terminal is visible but not focused.
+    Inactive,
+    /// The process is suspended, e.g. with Ctrl + z.
+    Paused,
+}
+
+impl AppLifecycleState {
+    fn as_str(&self) -> &'static str {
+        match self {
+            AppLifecycleState::Resumed => "AppLifecycleState.resumed",
+            AppLifecycleState::Inactive => "AppLifecycleState.inactive",
+            AppLifecycleState::Paused => "AppLifecycleState.paused",
+        }
+    }
+}
+
+/// Notifies the framework of the lifecycle state of the app.
+///
+/// The framework fills in the intermediate states, e.g. `hidden` when going
+/// from `inactive` to `paused`.
+pub(crate) fn send_lifecycle_state(
+    engine: &FlutterEngine,
+    state: AppLifecycleState,
+) -> Result<(), Error> {
+    // This channel uses the `StringCodec`, which is just UTF-8.
+    engine.send_platform_message(CHANNEL, state.as_str().as_bytes())
+}
+
+/// Like [send_lifecycle_state], but calls `on_handled` once the framework has
+/// handled the new state.
+pub(crate) fn send_lifecycle_state_with_reply(
+    engine: &FlutterEngine,
+    state: AppLifecycleState,
+    on_handled: Box<dyn FnOnce() + Send>,
+) -> Result<(), Error> {
+    engine.send_platform_message_with_reply(
+        CHANNEL,
+        state.as_str().as_bytes(),
+        Box::new(move |_| on_handled()),
+    )
+}
@@ -40,15 +40,56 @@ struct Args {
     #[arg(long)]
     no_kitty: bool,
 
+    /// How the app is drawn. By default, the best renderer which the terminal
+    /// supports is used.
+    #[arg(long, value_enum)]
+    renderer: Option<flt::RendererKind>,
+
+    /// The characters which the app is drawn with by the ANSI renderer. Glyphs
+    /// with more pixels per cell make text and thin lines more legible, but
+    /// need a font which supports them.
+    #[arg(long, value_enum, default_value_t)]
+    ansi_glyphs: flt::AnsiGlyphs,
+
+    /// The colors which the ANSI renderer uses. By default, this is guessed
+    /// from the `COLORTERM` and `TERM` environment variables.
+    #[arg(long, value_enum)]
+    color_depth: Option<flt::ColorDepth>,
+
+    /// How the ANSI renderer approximates colors when the color depth is
+    /// limited.
+    #[arg(long, value_enum, default_value_t)]
+    dithering: flt::Dithering,
+
     /// Disables GPU rendering (Metal) and forces software rendering.
     #[arg(long)]
     no_gpu: bool,
 
+    /// Number of pixels to scroll by for each scroll event, as the terminal
+    /// doesn't tell us how many lines the mouse has scrolled by.
+    #[arg(long, default_value_t = 10.0)]
+    scroll_delta: f64,
+
+    /// The maximum number of frames to draw per second. Fewer frames are drawn
+    /// when the terminal can't keep up, e.g. over SSH.
+    #[arg(long, default_value_t = 60.0, value_parser = parse_max_fps)]
+    max_fps: f64,
+
     /// Log to a file in addition to the terminal.
     #[arg(long)]
     log_file: Option<String>,
 }
 
+/// Parses the maximum frame rate, which has to be a positive number.
+fn parse_max_fps(value: &str) -> Result<f64, String> {
+    let max_fps: f64 = value.parse().map_err(|e| format!("{e}"))?;
+    if max_fps.is_finite() && max_fps > 0.0 {
+        Ok(max_fps)
+    } else {
+        Err(format!("expected a positive number, got {max_fps}"))
+    }
+}
+
 fn main() -> Result<(), flt::Error> {
     let args = Args::parse();
 
@@ -60,7 +101,13 @@ fn main() -> Result<(), flt::Error> {
         args.log_terminal_events,
         args.debug_semantics,
         args.no_kitty,
+        args.renderer,
+        args.ansi_glyphs,
+        args.color_depth,
+        args.dithering,
         args.no_gpu,
+        args.scroll_delta,
+        args.max_fps,
         args.log_file,
     )?;
 
@@ -68,3 +115,21 @@ fn main() -> Result<(), flt::Error> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_positive_max_fps() {
+        assert_eq!(parse_max_fps("30"), Ok(30.0));
+        assert_eq!(parse_max_fps("0.5"), Ok(0.5));
+    }
+
+    #[test]
+    fn rejects_max_fps_which_is_not_positive() {
+        for value in ["0", "-1", "NaN", "inf", "fast"] {
+            assert!(parse_max_fps(value).is_err(), "{value}");
+        }
+    }
+}
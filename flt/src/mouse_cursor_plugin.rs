@@ -0,0 +1,84 @@
+//! Implements the platform side of the `flutter/mousecursor` channel.
+//!
+//! See https://api.flutter.dev/flutter/services/SystemChannels/mouseCursor-constant.html.
+
+use crate::terminal_window::TerminalWindow;
+use flutter_sys::standard_codec::{self, Value};
+
+pub(crate) const CHANNEL: &str = "flutter/mousecursor";
+
+pub(crate) struct MouseCursorPlugin {
+    /// The pointer shape which was last set.
+    shape: Option<&'static str>,
+}
+
+impl MouseCursorPlugin {
+    pub(crate) fn new() -> Self {
+        Self { shape: None }
+    }
+
+    /// Sets the pointer shape for a method call of the framework, returning
+    /// `None` if the method isn't implemented.
+    pub(crate) fn handle_message(
+        &mut self,
+        terminal_window: &mut TerminalWindow,
+        message: &[u8],
+    ) -> Option<Vec<u8>> {
+        let (method, args) = standard_codec::decode_method_call(message)?;
+        if method != "activateSystemCursor" {
+            return None;
+        }
+
+        let kind = args.get("kind").and_then(Value::as_str).unwrap_or("basic");
+        let shape = to_pointer_shape(kind);
+        if self.shape != Some(shape) {
+            terminal_window.set_pointer_shape(shape).ok();
+            self.shape = Some(shape);
+        }
+
+        Some(standard_codec::encode_success_envelope(&Value::Null))
+    }
+}
+
+/// Maps the kind of a `SystemMouseCursor` to the name of a pointer shape.
+///
+/// See https://sw.kovidgoyal.net/kitty/pointer-shapes/#pointer-shape-names.
+fn to_pointer_shape(kind: &str) -> &'static str {
+    match kind {
+        "click" => "pointer",
+        "text" => "text",
+        "verticalText" => "vertical-text",
+        "forbidden" => "not-allowed",
+        "noDrop" => "no-drop",
+        "grab" => "grab",
+        "grabbing" => "grabbing",
+        "move" => "move",
+        "allScroll" => "all-scroll",
+        "contextMenu" => "context-menu",
+        "help" => "help",
+        "progress" => "progress",
+        "wait" => "wait",
+        "cell" => "cell",
+        "precise" => "crosshair",
+        "alias" => "alias",
+        "copy" => "copy",
+        "zoomIn" => "zoom-in",
+        "zoomOut" => "zoom-out",
+        "resizeColumn" => "col-resize",
+        "resizeRow" => "row-resize",
+        "resizeUp" => "n-resize",
+        "resizeDown" => "s-resize",
+        "resizeLeft" => "w-resize",
+        "resizeRight" => "e-resize",
+        "resizeUpLeft" => "nw-resize",
+        "resizeUpRight" => "ne-resize",
+        "resizeDownLeft" => "sw-resize",
+        "resizeDownRight" => "se-resize",
+        "resizeUpDown" => "ns-resize",
+        "resizeLeftRight" => "ew-resize",
+        "resizeUpLeftDownRight" => "nwse-resize",
+        "resizeUpRightDownLeft" => "nesw-resize",
+        // Terminals can't hide the pointer, so `none` also uses the default.
+        _ => "default",
+    }
+}
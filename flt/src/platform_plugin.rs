@@ -0,0 +1,83 @@
+//! Implements the platform side of the `flutter/platform` channel.
+//!
+//! See https://api.flutter.dev/flutter/services/SystemChannels/platform-constant.html.
+
+use crate::feature::ClipboardReply;
+use crate::terminal_window::TerminalWindow;
+use flutter_sys::json_codec;
+use serde_json::{json, Value};
+
+pub(crate) const CHANNEL: &str = "flutter/platform";
+
+pub(crate) struct PlatformPlugin {
+    /// The last text copied from the app, for when the terminal doesn't allow
+    /// reading the system clipboard.
+    clipboard: Option<String>,
+    /// Whether the terminal failed to reply when reading the clipboard, so it
+    /// isn't tried again.
+    clipboard_read_unsupported: bool,
+    /// Whether the clipboard was empty the last time it was read from the
+    /// terminal.
+    clipboard_read_empty: bool,
+}
+
+impl PlatformPlugin {
+    pub(crate) fn new() -> Self {
+        Self {
+            clipboard: None,
+            clipboard_read_unsupported: false,
+            clipboard_read_empty: false,
+        }
+    }
+
+    /// Answers a method call of the framework with the clipboard of the
+    /// terminal, returning `None` if the method isn't implemented.
+    pub(crate) fn handle_message(
+        &mut self,
+        terminal_window: &mut TerminalWindow,
+        message: &[u8],
+    ) -> Option<Vec<u8>> {
+        let (method, args) = json_codec::decode_method_call(message)?;
+
+        let result = match method.as_str() {
+            "Clipboard.setData" => {
+                let text = args.get("text").and_then(Value::as_str).unwrap_or_default();
+
+                terminal_window.write_clipboard(text).ok();
+                self.clipboard = Some(text.to_string());
+                Value::Null
+            }
+            "Clipboard.getData" => match self.read_clipboard(terminal_window) {
+                Some(text) => json!({ "text": text }),
+                None => Value::Null,
+            },
+            "Clipboard.hasStrings" => {
+                // This is called whenever a text selection toolbar is shown, so
+                // avoid querying the terminal, which might prompt the user and
+                // blocks this thread. Until the clipboard is read, assume it
+                // has text so that pasting is offered at all.
+                let has_strings = !(self.clipboard_read_unsupported || self.clipboard_read_empty)
+                    || self.clipboard.as_ref().is_some_and(|text| !text.is_empty());
+                json!({ "value": has_strings })
+            }
+            _ => return None,
+        };
+
+        Some(json_codec::encode_success_envelope(&result))
+    }
+
+    fn read_clipboard(&mut self, terminal_window: &mut TerminalWindow) -> Option<String> {
+        if !self.clipboard_read_unsupported {
+            match terminal_window.read_clipboard() {
+                ClipboardReply::Text(text) => {
+                    self.clipboard_read_empty = text.is_empty();
+                    return Some(text);
+                }
+                ClipboardReply::Unsupported => self.clipboard_read_unsupported = true,
+                // Might work the next time, e.g. if the reply was cut short.
+                ClipboardReply::Invalid => {}
+            }
+        }
+        self.clipboard.clone()
+    }
+}
@@ -0,0 +1,198 @@
+use flutter_sys::{FlutterPointerMouseButton, FlutterPointerPhase};
+
+/// Tracks the state of the mouse which the framework knows about.
+///
+/// The framework expects a pointer to be added before its other events and
+/// removed after them. In between, it expects the pointer to go down when its
+/// first button is pressed and up when its last button is released, with any
+/// other changes to the buttons in between reported as moves.
+#[derive(Default)]
+pub(crate) struct PointerState {
+    added: bool,
+    /// The last position of the pointer sent to the framework.
+    position: (f64, f64),
+    buttons: Vec<FlutterPointerMouseButton>,
+}
+
+impl PointerState {
+    /// Records that the pointer is at `position` in the app, returning whether
+    /// an add event should be sent to the framework before any other event.
+    pub(crate) fn add(&mut self, position: (f64, f64)) -> bool {
+        self.position = position;
+        !std::mem::replace(&mut self.added, true)
+    }
+
+    pub(crate) fn position(&self) -> (f64, f64) {
+        self.position
+    }
+
+    /// Records that the pointer left the app, returning the phases of the
+    /// events which should be sent to the framework.
+    ///
+    /// A pointer which is down is cancelled before it is removed.
+    pub(crate) fn remove(&mut self) -> Vec<FlutterPointerPhase> {
+        if !std::mem::replace(&mut self.added, false) {
+            return vec![];
+        }
+        if self.buttons.is_empty() {
+            vec![FlutterPointerPhase::Remove]
+        } else {
+            self.buttons.clear();
+            vec![FlutterPointerPhase::Cancel, FlutterPointerPhase::Remove]
+        }
+    }
+
+    /// The buttons which are currently pressed.
+    pub(crate) fn buttons(&self) -> Vec<FlutterPointerMouseButton> {
+        self.buttons.clone()
+    }
+
+    /// The phase of signal events, e.g. scrolls, which don't change the
+    /// buttons.
+    pub(crate) fn signal_phase(&self) -> FlutterPointerPhase {
+        if self.buttons.is_empty() {
+            FlutterPointerPhase::Hover
+        } else {
+            FlutterPointerPhase::Move
+        }
+    }
+
+    /// Records a press of `button`, returning the phase of the event which
+    /// should be sent to the framework.
+    pub(crate) fn press(&mut self, button: FlutterPointerMouseButton) -> FlutterPointerPhase {
+        let phase = if self.buttons.is_empty() {
+            FlutterPointerPhase::Down
+        } else {
+            FlutterPointerPhase::Move
+        };
+        if !self.buttons.contains(&button) {
+            self.buttons.push(button);
+        }
+        phase
+    }
+
+    /// Records a drag with `button`, returning the phase of the event which
+    /// should be sent to the framework.
+    ///
+    /// The press might not have been seen, e.g. when it happened outside of
+    /// the terminal, so this presses the button if needed.
+    pub(crate) fn drag(&mut self, button: FlutterPointerMouseButton) -> FlutterPointerPhase {
+        if self.buttons.contains(&button) {
+            FlutterPointerPhase::Move
+        } else {
+            self.press(button)
+        }
+    }
+
+    /// Records a release of `button`, returning the phase of the event which
+    /// should be sent to the framework, or `None` if it shouldn't be sent.
+    pub(crate) fn release(
+        &mut self,
+        button: FlutterPointerMouseButton,
+    ) -> Option<FlutterPointerPhase> {
+        if self.buttons.is_empty() {
+            return None;
+        }
+        // Some terminals don't report which button was released, in which
+        // case crossterm reports the left button, so release all of them.
+        if self.buttons.contains(&button) {
+            self.buttons.retain(|pressed| *pressed != button);
+        } else {
+            self.buttons.clear();
+        }
+
+        Some(if self.buttons.is_empty() {
+            FlutterPointerPhase::Up
+        } else {
+            FlutterPointerPhase::Move
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use FlutterPointerMouseButton::{Left, Right};
+    use FlutterPointerPhase::*;
+
+    #[test]
+    fn presses_drags_and_releases() {
+        let mut pointer = PointerState::default();
+        assert_eq!(pointer.signal_phase(), Hover);
+        assert_eq!(pointer.press(Left), Down);
+        assert_eq!(pointer.drag(Left), Move);
+        assert_eq!(pointer.signal_phase(), Move);
+        assert_eq!(pointer.release(Left), Some(Up));
+        assert_eq!(pointer.buttons(), []);
+        assert_eq!(pointer.signal_phase(), Hover);
+    }
+
+    #[test]
+    fn reports_other_buttons_as_moves() {
+        let mut pointer = PointerState::default();
+        assert_eq!(pointer.press(Left), Down);
+        assert_eq!(pointer.press(Right), Move);
+        assert_eq!(pointer.buttons(), [Left, Right]);
+        assert_eq!(pointer.release(Left), Some(Move));
+        assert_eq!(pointer.release(Right), Some(Up));
+    }
+
+    #[test]
+    fn presses_buttons_which_are_dragged_without_a_press() {
+        let mut pointer = PointerState::default();
+        assert_eq!(pointer.drag(Left), Down);
+        assert_eq!(pointer.drag(Left), Move);
+        assert_eq!(pointer.buttons(), [Left]);
+    }
+
+    #[test]
+    fn drops_releases_without_a_press() {
+        let mut pointer = PointerState::default();
+        assert_eq!(pointer.release(Left), None);
+    }
+
+    #[test]
+    fn releasing_an_unknown_button_releases_all_buttons() {
+        let mut pointer = PointerState::default();
+        pointer.press(Right);
+        pointer.press(FlutterPointerMouseButton::Middle);
+        assert_eq!(pointer.release(Left), Some(Up));
+        assert_eq!(pointer.buttons(), []);
+    }
+
+    #[test]
+    fn adds_the_pointer_once() {
+        let mut pointer = PointerState::default();
+        assert!(pointer.add((1.0, 2.0)));
+        assert!(!pointer.add((3.0, 4.0)));
+        assert_eq!(pointer.position(), (3.0, 4.0));
+    }
+
+    #[test]
+    fn removes_the_pointer_when_it_leaves() {
+        let mut pointer = PointerState::default();
+        assert_eq!(pointer.remove(), []);
+
+        pointer.add((0.0, 0.0));
+        assert_eq!(pointer.press(Left), Down);
+        assert_eq!(pointer.drag(Left), Move);
+        assert_eq!(pointer.release(Left), Some(Up));
+        assert_eq!(pointer.remove(), [Remove]);
+        assert_eq!(pointer.remove(), []);
+        assert!(pointer.add((0.0, 0.0)));
+    }
+
+    #[test]
+    fn cancels_a_pointer_which_leaves_while_down() {
+        let mut pointer = PointerState::default();
+        pointer.add((0.0, 0.0));
+        pointer.press(Left);
+        assert_eq!(pointer.remove(), [Cancel, Remove]);
+        assert_eq!(pointer.buttons(), []);
+
+        // The release happens outside of the app.
+        assert_eq!(pointer.release(Left), None);
+        assert!(pointer.add((0.0, 0.0)));
+        assert_eq!(pointer.press(Left), Down);
+    }
+}
@@ -0,0 +1,231 @@
+//! Draws frames on a separate thread, so that slow writes to the terminal don't
+//! hold up input and engine tasks on the platform thread.
+
+use crate::color::Rgb;
+use crate::event::PlatformEvent;
+use crate::renderer::{Frame, Renderer};
+use crossterm::cursor::MoveTo;
+use crossterm::style::Print;
+use crossterm::terminal::{BeginSynchronizedUpdate, Clear, ClearType, EndSynchronizedUpdate};
+use crossterm::QueueableCommand;
+use std::io::{stdout, Write};
+use std::mem;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Instant;
+
+/// A frame and the log pane below it, to be drawn by the [RenderThread].
+pub(crate) struct RenderJob {
+    pub(crate) buffer: Vec<u8>,
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    pub(crate) offset: (isize, isize),
+    pub(crate) background: Rgb,
+    pub(crate) terminal_size: (u16, u16),
+    /// A line for each row of the log pane, from the top.
+    pub(crate) logs: Vec<String>,
+    /// Whether the logs changed since the last job. Otherwise only the status
+    /// line is drawn.
+    pub(crate) logs_dirty: bool,
+    /// Shown at the end of the last line of the log pane.
+    pub(crate) status: String,
+}
+
+pub(crate) struct RenderThread {
+    shared: Arc<Shared>,
+    handle: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+    renderer: Mutex<Box<dyn Renderer>>,
+    pending: Mutex<Pending>,
+    condvar: Condvar,
+}
+
+#[derive(Default)]
+struct Pending {
+    /// The next job to draw. Jobs which are not drawn before the next one
+    /// arrives are dropped, so that the terminal only falls one frame behind.
+    job: Option<RenderJob>,
+    /// Changes to the renderer, which are applied before drawing the next job
+    /// so that the platform thread doesn't wait for the renderer.
+    resize: Option<(usize, usize)>,
+    clear: bool,
+    stopped: bool,
+}
+
+impl RenderThread {
+    /// Starts the thread, which reports the time taken to draw each frame with
+    /// [PlatformEvent::FrameDrawn].
+    pub(crate) fn new(
+        renderer: Box<dyn Renderer>,
+        synchronized_output: bool,
+        event_sender: Sender<PlatformEvent>,
+    ) -> Self {
+        let shared = Arc::new(Shared {
+            renderer: Mutex::new(renderer),
+            pending: Mutex::new(Pending::default()),
+            condvar: Condvar::new(),
+        });
+
+        let thread_shared = shared.clone();
+        let handle = thread::spawn(move || {
+            while let Some(mut renderer) = thread_shared.wait_for_job() {
+                // Taken after locking the renderer, so that jobs discarded
+                // while waiting for it are not drawn.
+                let mut pending = thread_shared.pending.lock().unwrap();
+                let Some(job) = pending.job.take() else {
+                    continue;
+                };
+                let (resize, clear) = (pending.resize.take(), mem::take(&mut pending.clear));
+                drop(pending);
+
+                if let Some((columns, rows)) = resize {
+                    renderer.resize(columns, rows);
+                }
+                if clear {
+                    renderer.clear();
+                }
+
+                let start_instant = Instant::now();
+                let result = draw(renderer.as_mut(), &job, synchronized_output)
+                    .map(|_| start_instant.elapsed());
+                drop(renderer);
+
+                if event_sender
+                    .send(PlatformEvent::FrameDrawn(result))
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        Self {
+            shared,
+            handle: Some(handle),
+        }
+    }
+
+    /// Queues `job` to be drawn, replacing the job which is yet to be drawn.
+    pub(crate) fn submit(&self, mut job: RenderJob) {
+        let mut pending = self.shared.pending.lock().unwrap();
+        if let Some(stale) = pending.job.take() {
+            job.logs_dirty |= stale.logs_dirty;
+        }
+        pending.job = Some(job);
+        self.shared.condvar.notify_all();
+    }
+
+    /// Drops the job which is yet to be drawn, e.g. when the screen is about to
+    /// be used for something else.
+    pub(crate) fn discard_pending(&self) {
+        self.shared.pending.lock().unwrap().job = None;
+    }
+
+    /// Resizes the renderer before the next job is drawn, see
+    /// [Renderer::resize].
+    pub(crate) fn resize(&self, columns: usize, rows: usize) {
+        self.shared.pending.lock().unwrap().resize = Some((columns, rows));
+    }
+
+    /// Makes the renderer draw the next job in full, see [Renderer::clear].
+    pub(crate) fn clear(&self) {
+        self.shared.pending.lock().unwrap().clear = true;
+    }
+
+    /// Locks the renderer, waiting for the frame which is being drawn.
+    ///
+    /// Prefer the methods which apply changes before the next job, as this
+    /// blocks for as long as drawing a frame takes.
+    pub(crate) fn renderer(&self) -> MutexGuard<'_, Box<dyn Renderer>> {
+        self.shared.renderer.lock().unwrap()
+    }
+}
+
+impl Drop for RenderThread {
+    fn drop(&mut self) {
+        self.shared.pending.lock().unwrap().stopped = true;
+        self.shared.condvar.notify_all();
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Shared {
+    /// Blocks until there is a job, then returns the locked renderer to draw it
+    /// with, or `None` when the thread should stop.
+    fn wait_for_job(&self) -> Option<MutexGuard<'_, Box<dyn Renderer>>> {
+        let pending = self.pending.lock().unwrap();
+        let pending = self
+            .condvar
+            .wait_while(pending, |pending| pending.job.is_none() && !pending.stopped)
+            .unwrap();
+        if pending.stopped {
+            return None;
+        }
+        drop(pending);
+
+        Some(self.renderer.lock().unwrap())
+    }
+}
+
+/// Draws `job` while holding the lock on stdout, so that writes from other
+/// threads don't end up in the middle of the frame.
+fn draw(
+    renderer: &mut dyn Renderer,
+    job: &RenderJob,
+    synchronized_output: bool,
+) -> Result<(), std::io::Error> {
+    let mut stdout = stdout().lock();
+
+    // Nothing is shown until the end of the update, so that the frame and
+    // the logs don't tear.
+    if synchronized_output {
+        stdout.queue(BeginSynchronizedUpdate)?;
+    }
+
+    renderer.present(
+        &mut stdout,
+        &Frame {
+            buffer: &job.buffer,
+            width: job.width,
+            height: job.height,
+            offset: job.offset,
+            background: job.background,
+        },
+    )?;
+
+    let (cols, rows) = job.terminal_size;
+    let (cols, rows) = (cols as usize, rows as usize);
+    let log_rows = job.logs.len();
+
+    if rows >= log_rows {
+        for i in 0..log_rows {
+            let is_last_line = i == log_rows - 1;
+            if !job.logs_dirty && !is_last_line {
+                continue;
+            }
+
+            let y = rows - log_rows + i;
+            stdout.queue(MoveTo(0, y as u16))?;
+            stdout.queue(Clear(ClearType::CurrentLine))?;
+            stdout.queue(Print(&job.logs[i]))?;
+
+            if is_last_line {
+                stdout.queue(MoveTo(
+                    cols.saturating_sub(job.status.len()) as u16,
+                    y as u16,
+                ))?;
+                stdout.queue(Print(&job.status))?;
+            }
+        }
+    }
+
+    if synchronized_output {
+        stdout.queue(EndSynchronizedUpdate)?;
+    }
+    stdout.flush()
+}
@@ -0,0 +1,118 @@
+//! Backends which draw frames from the engine to the terminal.
+
+use crate::ansi::{AnsiGlyphs, AnsiRenderer};
+use crate::color::{ColorDepth, Dithering, Rgb};
+use crate::iterm2::Iterm2Renderer;
+use crate::kitty::{KittyRenderer, KittySupport};
+use crate::sixel::SixelRenderer;
+use std::io::{Stdout, Write};
+
+/// Draws frames from the engine.
+///
+/// Output is written to `out` instead of the terminal directly, so that
+/// renderers can be used with any writer.
+///
+/// Frames are drawn on the [crate::render_thread::RenderThread].
+pub(crate) trait Renderer: Send {
+    /// Whether frames are drawn in pixels, as opposed to in cells.
+    fn draws_pixels(&self) -> bool;
+
+    /// Draws `frame`.
+    fn present(&mut self, out: &mut dyn Write, frame: &Frame) -> std::io::Result<()>;
+
+    /// Called when the area for the app changes to `columns` by `rows` cells.
+    fn resize(&mut self, _columns: usize, _rows: usize) {}
+
+    /// Forgets what was drawn, e.g. after the screen was cleared, so that the
+    /// next frame is drawn in full.
+    fn clear(&mut self) {}
+
+    /// Removes what was drawn, before the terminal is restored.
+    fn teardown(&mut self, _out: &mut dyn Write) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A frame from the engine, in its pixel format.
+pub(crate) struct Frame<'a> {
+    pub(crate) buffer: &'a [u8],
+    pub(crate) width: usize,
+    pub(crate) height: usize,
+    /// The offset of the frame in pixels, when the viewport is panned.
+    pub(crate) offset: (isize, isize),
+    /// The background color of the terminal, which transparent pixels and
+    /// the area outside the frame are drawn with.
+    pub(crate) background: Rgb,
+}
+
+/// The available renderers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum RendererKind {
+    /// Block or braille characters with ANSI colors, which should work on most
+    /// terminals.
+    Ansi,
+    /// The kitty graphics protocol.
+    Kitty,
+    /// The iTerm2 inline images protocol.
+    Iterm2,
+    /// Sixel graphics.
+    Sixel,
+}
+
+/// A renderer, with what the terminal supports for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum RendererConfig {
+    Ansi,
+    Kitty(KittySupport),
+    Iterm2,
+    Sixel,
+}
+
+impl RendererKind {
+    /// Finds the best renderer which the terminal supports, unless `kind` is
+    /// given, and queries what the terminal supports for it.
+    ///
+    /// This must be called in raw mode.
+    pub(crate) fn detect(
+        kind: Option<Self>,
+        stdout: &mut Stdout,
+        disable_kitty: bool,
+    ) -> RendererConfig {
+        let kind = kind.unwrap_or_else(|| {
+            if !disable_kitty && crate::feature::kitty_graphics_supported(stdout) {
+                RendererKind::Kitty
+            } else if crate::feature::iterm2_images_supported(stdout) {
+                RendererKind::Iterm2
+            } else if crate::feature::sixel_supported(stdout) {
+                RendererKind::Sixel
+            } else {
+                RendererKind::Ansi
+            }
+        });
+
+        match kind {
+            RendererKind::Ansi => RendererConfig::Ansi,
+            RendererKind::Kitty => RendererConfig::Kitty(KittySupport::detect(stdout)),
+            RendererKind::Iterm2 => RendererConfig::Iterm2,
+            RendererKind::Sixel => RendererConfig::Sixel,
+        }
+    }
+}
+
+impl RendererConfig {
+    pub(crate) fn create(
+        self,
+        ansi_glyphs: AnsiGlyphs,
+        color_depth: ColorDepth,
+        dithering: Dithering,
+    ) -> Box<dyn Renderer> {
+        match self {
+            RendererConfig::Ansi => {
+                Box::new(AnsiRenderer::new(ansi_glyphs, color_depth, dithering))
+            }
+            RendererConfig::Kitty(support) => Box::new(KittyRenderer::new(support)),
+            RendererConfig::Iterm2 => Box::new(Iterm2Renderer),
+            RendererConfig::Sixel => Box::new(SixelRenderer),
+        }
+    }
+}
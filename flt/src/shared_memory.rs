@@ -0,0 +1,226 @@
+//! Memory shared with the terminal, for transmitting frames with the kitty
+//! graphics protocol.
+
+use libc::{ftruncate, shm_open, shm_unlink, O_CREAT, O_RDWR, O_TRUNC};
+use memmap2::MmapMut;
+use std::fs::{File, FileTimes};
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::FromRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant, SystemTime};
+
+/// A POSIX shared memory object, which the terminal unlinks after reading it.
+pub(crate) struct SharedMemoryBuffer {
+    pub(crate) name: String,
+    // We keep file to keep the FD open (though shm persists until unlinked/closed)
+    // and to allow resizing (if we used File methods).
+    // But we used libc::shm_open which returns raw fd.
+    // We can wrap it in ManuallyDrop<File> or just raw fd.
+    // memmap2 `map_mut` takes &File.
+    #[allow(dead_code)]
+    file: File,
+    pub(crate) map: Option<MmapMut>,
+}
+
+impl SharedMemoryBuffer {
+    pub(crate) fn new(size: usize, suffix: u64) -> std::io::Result<Self> {
+        let pid = std::process::id();
+        let name = format!("/flt_{}_{}", pid, suffix);
+        let c_name = std::ffi::CString::new(name.clone())?;
+
+        // Ensure clean state
+        unsafe { shm_unlink(c_name.as_ptr()) };
+
+        let fd = unsafe { shm_open(c_name.as_ptr(), O_RDWR | O_CREAT | O_TRUNC, 0o600) };
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        let file = unsafe { File::from_raw_fd(fd) };
+
+        if unsafe { ftruncate(fd, size as i64) } < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        let map = unsafe { MmapMut::map_mut(&file)? };
+
+        Ok(Self {
+            name,
+            file,
+            map: Some(map),
+        })
+    }
+}
+
+impl Drop for SharedMemoryBuffer {
+    fn drop(&mut self) {
+        if let Ok(c_name) = std::ffi::CString::new(self.name.clone()) {
+            unsafe { shm_unlink(c_name.as_ptr()) };
+        }
+    }
+}
+
+/// The number of frames which can be in flight in a [SharedMemoryRing].
+const RING_SIZE: usize = 3;
+
+/// How long to wait for the terminal to read a slot of a [SharedMemoryRing]
+/// before overwriting it anyway.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Files in shared memory which are reused across frames, as the terminal
+/// reads them without deleting them, unlike [SharedMemoryBuffer].
+///
+/// Slots are only reused after the terminal has read them, which is detected
+/// by the access time of the file being updated.
+pub(crate) struct SharedMemoryRing {
+    slots: Vec<File>,
+    paths: Vec<PathBuf>,
+    /// Whether each slot has a frame which the terminal might not have read.
+    written: Vec<bool>,
+    /// The next slot to write to, which is the one written the longest ago.
+    next: usize,
+    size: usize,
+    /// Whether the access time of the slots is updated when the terminal reads
+    /// them, which isn't the case on filesystems mounted with `noatime`.
+    handshake: bool,
+}
+
+impl SharedMemoryRing {
+    /// Fails where there is no `/dev/shm`, e.g. on macOS, as other
+    /// directories for files might not be in memory.
+    ///
+    /// Without the `handshake`, slots are reused without waiting for the
+    /// terminal, see [Self::verify_handshake].
+    pub(crate) fn new(size: usize, handshake: bool) -> std::io::Result<Self> {
+        let dir = Path::new("/dev/shm");
+        if !dir.is_dir() {
+            return Err(std::io::ErrorKind::Unsupported.into());
+        }
+
+        // Unique to each ring, so that rings don't share files.
+        static RINGS: AtomicUsize = AtomicUsize::new(0);
+        let ring = RINGS.fetch_add(1, Ordering::Relaxed);
+
+        let pid = std::process::id();
+        let paths: Vec<_> = (0..RING_SIZE)
+            .map(|i| dir.join(format!("flt_{}_ring_{}_{}", pid, ring, i)))
+            .collect();
+        let slots = paths
+            .iter()
+            .map(|path| {
+                let file = File::options()
+                    .read(true)
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .open(path)?;
+                file.set_len(size as u64)?;
+                Ok(file)
+            })
+            .collect::<std::io::Result<_>>()?;
+
+        Ok(Self {
+            slots,
+            paths,
+            written: vec![false; RING_SIZE],
+            next: 0,
+            size,
+            handshake,
+        })
+    }
+
+    /// Writes `frame` to the next slot once the terminal has read it, and
+    /// returns the path of the slot.
+    pub(crate) fn write(&mut self, frame: &[u8]) -> std::io::Result<&Path> {
+        if frame.len() != self.size {
+            // Only reallocated when the frame is resized, after the terminal
+            // is done with every slot, as it would read truncated frames.
+            for slot in 0..RING_SIZE {
+                self.wait_until_read(slot)?;
+                self.slots[slot].set_len(frame.len() as u64)?;
+                self.written[slot] = false;
+            }
+            self.size = frame.len();
+        }
+
+        let slot = self.next;
+        self.next = (self.next + 1) % RING_SIZE;
+
+        self.wait_until_read(slot)?;
+        self.slots[slot].write_all_at(frame, 0)?;
+        // File times are coarse, so reset the access time instead of relying on
+        // it being earlier than the write.
+        self.slots[slot].set_times(FileTimes::new().set_accessed(SystemTime::UNIX_EPOCH))?;
+        self.written[slot] = true;
+        Ok(&self.paths[slot])
+    }
+
+    /// Checks if the access time of the last slot which was written was
+    /// updated, after the terminal was asked to read it. Otherwise, slots are
+    /// reused without waiting for the terminal.
+    pub(crate) fn verify_handshake(&mut self) -> bool {
+        let slot = (self.next + RING_SIZE - 1) % RING_SIZE;
+        self.handshake = self.is_read(slot).unwrap_or(false);
+        self.handshake
+    }
+
+    /// Waits until the terminal has read `slot`, if it was written, or until
+    /// the timeout.
+    fn wait_until_read(&self, slot: usize) -> std::io::Result<()> {
+        if !self.handshake || !self.written[slot] {
+            return Ok(());
+        }
+
+        let start = Instant::now();
+        while !self.is_read(slot)? && start.elapsed() <= HANDSHAKE_TIMEOUT {
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        Ok(())
+    }
+
+    /// Whether the terminal has read `slot` since it was last written.
+    fn is_read(&self, slot: usize) -> std::io::Result<bool> {
+        let metadata = self.slots[slot].metadata()?;
+        Ok(metadata.accessed()? >= metadata.modified()?)
+    }
+}
+
+impl Drop for SharedMemoryRing {
+    fn drop(&mut self) {
+        for path in &self.paths {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuses_slots() {
+        let mut ring = SharedMemoryRing::new(4, true).unwrap();
+
+        let paths: Vec<_> = (0..RING_SIZE as u8 * 2)
+            .map(|i| {
+                let path = ring.write(&[i; 4]).unwrap().to_path_buf();
+                // Read it like the terminal would.
+                assert_eq!(std::fs::read(&path).unwrap(), [i; 4]);
+                path
+            })
+            .collect();
+
+        assert_eq!(paths[..RING_SIZE], paths[RING_SIZE..]);
+    }
+
+    #[test]
+    fn resizes_slots() {
+        let mut ring = SharedMemoryRing::new(4, true).unwrap();
+        let path = ring.write(&[1; 4]).unwrap().to_path_buf();
+        std::fs::read(path).unwrap();
+
+        let path = ring.write(&[2; 8]).unwrap().to_path_buf();
+        assert_eq!(std::fs::read(path).unwrap(), [2; 8]);
+    }
+}
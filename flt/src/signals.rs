@@ -0,0 +1,75 @@
+//! Handles the signals for suspending and continuing the process, which can
+//! also be sent from outside, e.g. with `kill -TSTP`.
+
+use crate::event::PlatformEvent;
+use libc::{c_int, c_void, SIGCONT, SIGTSTP, SIG_DFL};
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::mpsc::Sender;
+use std::thread;
+
+/// The end of the pipe which the signal handler writes the signal to.
+static PIPE_WRITE_FD: AtomicI32 = AtomicI32::new(-1);
+
+/// Only does what is safe in a signal handler, which is writing to the pipe.
+extern "C" fn handle_signal(signal: c_int) {
+    let byte = signal as u8;
+    unsafe {
+        libc::write(
+            PIPE_WRITE_FD.load(Ordering::Relaxed),
+            &byte as *const u8 as *const c_void,
+            1,
+        )
+    };
+}
+
+/// Sends [PlatformEvent::SuspendRequested] on SIGTSTP instead of stopping the
+/// process, and [PlatformEvent::Continued] on SIGCONT.
+pub(crate) fn forward_signals(event_sender: Sender<PlatformEvent>) -> std::io::Result<()> {
+    let mut fds = [0; 2];
+    if unsafe { libc::pipe(fds.as_mut_ptr()) } < 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+    let [read_fd, write_fd] = fds;
+    PIPE_WRITE_FD.store(write_fd, Ordering::Relaxed);
+
+    for signal in [SIGTSTP, SIGCONT] {
+        unsafe { libc::signal(signal, handle_signal as libc::sighandler_t) };
+    }
+
+    thread::spawn(move || loop {
+        let mut byte = 0u8;
+        let n = unsafe { libc::read(read_fd, &mut byte as *mut u8 as *mut c_void, 1) };
+        if n < 0 && std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted {
+            continue;
+        }
+        if n <= 0 {
+            break;
+        }
+
+        let event = match byte as c_int {
+            SIGTSTP => PlatformEvent::SuspendRequested,
+            SIGCONT => PlatformEvent::Continued,
+            _ => continue,
+        };
+        if event_sender.send(event).is_err() {
+            break;
+        }
+    });
+    Ok(())
+}
+
+/// Stops every process in the process group like the shell does for Ctrl + z,
+/// returning after the process is continued (e.g. with `fg`).
+///
+/// Stopping only this process would leave its parents, e.g. `cargo run`,
+/// waiting for it, so the shell wouldn't get the terminal back.
+pub(crate) fn stop_process_group() {
+    unsafe {
+        // Otherwise this process would handle the signal instead of stopping.
+        libc::signal(SIGTSTP, SIG_DFL);
+        libc::kill(0, SIGTSTP);
+        if PIPE_WRITE_FD.load(Ordering::Relaxed) >= 0 {
+            libc::signal(SIGTSTP, handle_signal as libc::sighandler_t);
+        }
+    }
+}
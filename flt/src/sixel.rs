@@ -0,0 +1,264 @@
+//! Draws frames as Sixel graphics.
+//!
+//! See https://vt100.net/docs/vt3xx-gp/chapter14.html.
+
+use crate::color::{composite, Rgb};
+use crate::renderer::{Frame, Renderer};
+use crossterm::cursor::MoveTo;
+use crossterm::style::Print;
+use crossterm::QueueableCommand;
+use std::fmt::Write;
+
+/// Levels of each channel in the palette, which has 6 * 7 * 6 = 252 colors to
+/// fit in the 256 color registers most terminals have. Green gets an extra
+/// level as the eye is most sensitive to it.
+const RED_LEVELS: usize = 6;
+const GREEN_LEVELS: usize = 7;
+const BLUE_LEVELS: usize = 6;
+const PALETTE_SIZE: usize = RED_LEVELS * GREEN_LEVELS * BLUE_LEVELS;
+
+pub(crate) struct SixelRenderer;
+
+impl Renderer for SixelRenderer {
+    fn draws_pixels(&self) -> bool {
+        true
+    }
+
+    fn present(&mut self, out: &mut dyn std::io::Write, frame: &Frame) -> std::io::Result<()> {
+        if frame.buffer.is_empty() {
+            return Ok(());
+        }
+
+        // The image is drawn from the cursor.
+        out.queue(MoveTo(0, 0))?;
+        out.queue(Print(encode(
+            frame.buffer,
+            frame.width,
+            frame.height,
+            frame.background,
+        )))?;
+        out.flush()
+    }
+}
+
+/// Encodes `buffer`, which is a frame from the engine, as a Sixel image over
+/// `background`.
+///
+/// Colors are quantized to a fixed palette and dithered with Floyd-Steinberg
+/// error diffusion.
+fn encode(buffer: &[u8], width: usize, height: usize, background: Rgb) -> String {
+    let indices = quantize(buffer, width, height, background);
+
+    let mut output = String::new();
+
+    // \x1bP0;1;0q : Start a Sixel image, where pixels which are not set keep
+    //               the background.
+    // "1;1;w;h    : Raster attributes, with a 1:1 aspect ratio.
+    write!(output, "\x1bP0;1;0q\"1;1;{};{}", width, height).unwrap();
+
+    // Define the palette, with channels in percentages.
+    for index in 0..PALETTE_SIZE {
+        let (r, g, b) = palette_color(index);
+        write!(
+            output,
+            "#{};2;{};{};{}",
+            index,
+            r as usize * 100 / 255,
+            g as usize * 100 / 255,
+            b as usize * 100 / 255,
+        )
+        .unwrap();
+    }
+
+    // Each band of 6 rows is drawn once for every color in it, with each
+    // character setting the pixels of that color in a column.
+    let mut sixels = vec![0u8; width];
+    for band_start in (0..height).step_by(6) {
+        let band_rows = (height - band_start).min(6);
+        let band = &indices[band_start * width..(band_start + band_rows) * width];
+
+        let mut colors = [false; PALETTE_SIZE];
+        for &index in band {
+            colors[index as usize] = true;
+        }
+
+        let mut first_color = true;
+        for (color, _) in colors.iter().enumerate().filter(|(_, used)| **used) {
+            sixels.fill(0);
+            for (row, pixels) in band.chunks_exact(width).enumerate() {
+                for (sixel, &index) in sixels.iter_mut().zip(pixels) {
+                    if index as usize == color {
+                        *sixel |= 1 << row;
+                    }
+                }
+            }
+
+            if !first_color {
+                // Return to the start of the band.
+                output.push('$');
+            }
+            first_color = false;
+
+            write!(output, "#{}", color).unwrap();
+            write_run_length_encoded(&mut output, &sixels);
+        }
+
+        // Move to the next band. This isn't done after the last band, as it
+        // would scroll the screen when the image ends at the bottom.
+        if band_start + 6 < height {
+            output.push('-');
+        }
+    }
+
+    // String terminator.
+    output.push_str("\x1b\\");
+    output
+}
+
+/// Writes `sixels` as characters, compressing repeated characters.
+fn write_run_length_encoded(output: &mut String, sixels: &[u8]) {
+    // Trailing empty sixels don't need to be drawn.
+    let len = sixels
+        .iter()
+        .rposition(|sixel| *sixel != 0)
+        .map_or(0, |i| i + 1);
+
+    let mut i = 0;
+    while i < len {
+        let sixel = sixels[i];
+        let run = sixels[i..len].iter().take_while(|s| **s == sixel).count();
+        let c = (b'?' + sixel) as char;
+        if run > 3 {
+            write!(output, "!{}{}", run, c).unwrap();
+        } else {
+            for _ in 0..run {
+                output.push(c);
+            }
+        }
+        i += run;
+    }
+}
+
+/// Maps every pixel of `buffer` to the index of a palette color.
+fn quantize(buffer: &[u8], width: usize, height: usize, background: Rgb) -> Vec<u8> {
+    let mut indices = vec![0u8; width * height];
+
+    // The quantization error which is carried to the current and next rows, for
+    // each channel of each pixel.
+    let mut errors = vec![[0i16; 3]; width];
+    let mut next_errors = vec![[0i16; 3]; width];
+
+    for y in 0..height {
+        for x in 0..width {
+            let (r, g, b) = composite(&buffer[(y * width + x) * 4..], background);
+            let error = errors[x];
+            let color = [r, g, b].map(|channel| channel as i16);
+            let color = [0, 1, 2].map(|i| (color[i] + error[i]).clamp(0, 255) as u8);
+
+            let index = nearest_palette_index(color[0], color[1], color[2]);
+            indices[y * width + x] = index as u8;
+
+            let (pr, pg, pb) = palette_color(index);
+            for (channel, palette_value) in [pr, pg, pb].into_iter().enumerate() {
+                let error = color[channel] as i16 - palette_value as i16;
+                if x + 1 < width {
+                    errors[x + 1][channel] += error * 7 / 16;
+                    next_errors[x + 1][channel] += error / 16;
+                }
+                if x > 0 {
+                    next_errors[x - 1][channel] += error * 3 / 16;
+                }
+                next_errors[x][channel] += error * 5 / 16;
+            }
+        }
+
+        std::mem::swap(&mut errors, &mut next_errors);
+        next_errors.fill([0; 3]);
+    }
+
+    indices
+}
+
+fn nearest_palette_index(r: u8, g: u8, b: u8) -> usize {
+    let level = |value: u8, levels: usize| (value as usize * (levels - 1) + 127) / 255;
+    (level(r, RED_LEVELS) * GREEN_LEVELS + level(g, GREEN_LEVELS)) * BLUE_LEVELS
+        + level(b, BLUE_LEVELS)
+}
+
+fn palette_color(index: usize) -> (u8, u8, u8) {
+    let value = |level: usize, levels: usize| (level * 255 / (levels - 1)) as u8;
+    (
+        value(index / (GREEN_LEVELS * BLUE_LEVELS), RED_LEVELS),
+        value(index / BLUE_LEVELS % GREEN_LEVELS, GREEN_LEVELS),
+        value(index % BLUE_LEVELS, BLUE_LEVELS),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::rgba_to_engine;
+
+    #[test]
+    fn presents_sixel_image() {
+        let buffer = rgba_to_engine([255, 255, 255, 255]);
+        let frame = Frame {
+            buffer: &buffer,
+            width: 1,
+            height: 1,
+            offset: (0, 0),
+            background: (0, 0, 0),
+        };
+
+        let mut out = vec![];
+        SixelRenderer.present(&mut out, &frame).unwrap();
+        let out = String::from_utf8(out).unwrap();
+
+        assert!(out.starts_with("\x1b[1;1H\x1bP0;1;0q\"1;1;1;1#0;2;0;0;0"));
+        // The last color of the palette is white, which sets the top pixel of
+        // the first band.
+        assert!(out.ends_with("#251@\x1b\\"));
+    }
+
+    /// The sixel data of `pixels`, which are RGBA, after the palette.
+    fn encode_pixels(pixels: &[[u8; 4]], width: usize, height: usize) -> String {
+        let buffer: Vec<u8> = pixels.iter().copied().flat_map(rgba_to_engine).collect();
+        let output = encode(&buffer, width, height, (0, 0, 0));
+        let (_, data) = output.split_once("#251;2;100;100;100").unwrap();
+        data.to_string()
+    }
+
+    #[test]
+    fn encodes_bands_of_six_rows() {
+        let white = [255, 255, 255, 255];
+        assert_eq!(encode_pixels(&[white; 7], 1, 7), "#251~-#251@\x1b\\");
+    }
+
+    #[test]
+    fn encodes_every_color_of_a_band() {
+        let pixels = [[0, 0, 0, 255], [255, 255, 255, 255]];
+        assert_eq!(encode_pixels(&pixels, 2, 1), "#0@$#251?@\x1b\\");
+    }
+
+    #[test]
+    fn run_length_encodes_repeated_sixels() {
+        let encode = |sixels: &[u8]| {
+            let mut output = String::new();
+            write_run_length_encoded(&mut output, sixels);
+            output
+        };
+        assert_eq!(encode(&[1, 1, 1, 1, 1, 2]), "!5@A");
+        assert_eq!(encode(&[1, 1, 1, 2]), "@@@A");
+        assert_eq!(encode(&[0, 1, 0, 0]), "?@");
+        assert_eq!(encode(&[0, 0]), "");
+    }
+
+    #[test]
+    fn maps_palette_colors_to_themselves() {
+        for index in 0..PALETTE_SIZE {
+            let (r, g, b) = palette_color(index);
+            assert_eq!(nearest_palette_index(r, g, b), index);
+        }
+        assert_eq!(palette_color(nearest_palette_index(250, 5, 0)), (255, 0, 0));
+    }
+}
@@ -1,31 +1,125 @@
 use flutter_sys::{EngineTask, Error, FlutterEngine};
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::time::Duration;
 
 pub(crate) struct TaskRunner {
-    tasks: Vec<EngineTask>,
+    /// Ordered so that the task which should run first is at the top.
+    tasks: BinaryHeap<Reverse<QueuedTask>>,
+    /// The number of tasks posted so far, so that tasks with the same target
+    /// time run in the order they were posted.
+    posted: u64,
 }
 
 impl TaskRunner {
     pub(crate) fn new() -> Self {
-        Self { tasks: vec![] }
+        Self {
+            tasks: BinaryHeap::new(),
+            posted: 0,
+        }
     }
 
     pub(crate) fn post_task(&mut self, task: EngineTask) {
-        self.tasks.push(task);
+        self.tasks.push(Reverse(QueuedTask {
+            order: self.posted,
+            task,
+        }));
+        self.posted += 1;
     }
 
     pub(crate) fn run_expired_tasks(&mut self, engine: &FlutterEngine) -> Result<(), Error> {
-        let mut not_run_tasks = vec![];
-        // TODO(jiahaog): Use nightly drain_filter.
-        // TODO(jiahaog): This is slow, consider a priority queue.
-        for task in self.tasks.drain(..) {
-            if task.can_run_now() {
-                task.run(engine)?;
-            } else {
-                not_run_tasks.push(task);
+        while let Some(Reverse(next)) = self.tasks.peek() {
+            if !next.task.can_run_now() {
+                break;
             }
+            let Reverse(next) = self.tasks.pop().unwrap();
+            next.task.run(engine)?;
         }
-
-        self.tasks = not_run_tasks;
         Ok(())
     }
+
+    /// How long until the next task should run, or `None` if there are no
+    /// tasks.
+    pub(crate) fn time_until_next_task(&self) -> Option<Duration> {
+        self.tasks.peek().map(|Reverse(next)| {
+            Duration::from_nanos(
+                next.task
+                    .target_time_nanos()
+                    .saturating_sub(flutter_sys::current_time_nanos()),
+            )
+        })
+    }
+}
+
+struct QueuedTask {
+    order: u64,
+    task: EngineTask,
+}
+
+impl QueuedTask {
+    fn key(&self) -> (u64, u64) {
+        (self.task.target_time_nanos(), self.order)
+    }
+}
+
+impl Ord for QueuedTask {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl PartialOrd for QueuedTask {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for QueuedTask {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for QueuedTask {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flutter_sys::sys;
+
+    fn task(target_time_nanos: u64) -> EngineTask {
+        // Never run, so it doesn't need to be a task from the engine.
+        let flutter_task: sys::FlutterTask = unsafe { std::mem::zeroed() };
+        EngineTask::new(target_time_nanos, flutter_task)
+    }
+
+    /// The target times and the order the tasks were posted in, in the order
+    /// they run.
+    fn run_order(task_runner: TaskRunner) -> Vec<(u64, u64)> {
+        let tasks = task_runner.tasks.into_sorted_vec();
+        tasks.iter().rev().map(|Reverse(task)| task.key()).collect()
+    }
+
+    #[test]
+    fn runs_the_earliest_task_first() {
+        let mut task_runner = TaskRunner::new();
+        for target_time_nanos in [30, 10, 20] {
+            task_runner.post_task(task(target_time_nanos));
+        }
+        assert_eq!(run_order(task_runner), [(10, 1), (20, 2), (30, 0)]);
+    }
+
+    #[test]
+    fn runs_tasks_with_the_same_target_time_in_posting_order() {
+        let mut task_runner = TaskRunner::new();
+        for target_time_nanos in [10, 5, 10, 10] {
+            task_runner.post_task(task(target_time_nanos));
+        }
+        assert_eq!(run_order(task_runner), [(5, 1), (10, 0), (10, 2), (10, 3)]);
+    }
+
+    #[test]
+    fn has_no_next_task_when_empty() {
+        assert_eq!(TaskRunner::new().time_until_next_task(), None);
+    }
 }
@@ -1,11 +1,17 @@
 use crate::{
-    constants::{SCROLL_DELTA, ZOOM_FACTOR},
+    constants::ZOOM_FACTOR,
+    event::{EngineEvent, PlatformEvent},
+    keyboard::{self, FlutterKey, KEY_EVENT_CHANNEL},
+    lifecycle::{send_lifecycle_state, send_lifecycle_state_with_reply, AppLifecycleState},
     Error, TerminalEmbedder,
 };
 use crossterm::event::{
-    Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+    Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+use flutter_sys::{
+    text_input::TextInputKey, FlutterKeyEventType, FlutterPointerMouseButton, FlutterPointerPhase,
+    FlutterPointerSignalKind,
 };
-use flutter_sys::{FlutterPointerMouseButton, FlutterPointerPhase, FlutterPointerSignalKind};
 
 /// Modifier to intercept events which will not be forwarded to Flutter.
 const CONTROL_KEY: KeyModifiers = KeyModifiers::CONTROL;
@@ -17,31 +23,35 @@ impl TerminalEmbedder {
         }
 
         match event {
-            crossterm::event::Event::FocusGained => todo!(),
-            crossterm::event::Event::FocusLost => todo!(),
-            crossterm::event::Event::Key(KeyEvent {
-                code, modifiers, ..
-            }) => {
-                if modifiers == CONTROL_KEY {
-                    return self.handle_control_char(code);
-                }
-                if code == KeyCode::Char('?') {
-                    self.terminal_window.toggle_show_help()?;
-                    return Ok(());
-                }
-
-                // The following only supports IME text fields.
-                // TODO(jiahaog): Non-IME key support which calls `FlutterEngineSendKeyEvent`.
-                match code {
-                    KeyCode::Char(c) => {
-                        self.engine.send_text_input_char(c).ok();
+            crossterm::event::Event::FocusGained => {
+                send_lifecycle_state(&self.engine, AppLifecycleState::Resumed)?;
+                Ok(())
+            }
+            crossterm::event::Event::FocusLost => {
+                self.remove_pointer()?;
+                self.release_pressed_keys()?;
+                send_lifecycle_state(&self.engine, AppLifecycleState::Inactive)?;
+                Ok(())
+            }
+            crossterm::event::Event::Key(
+                key_event @ KeyEvent {
+                    code,
+                    modifiers,
+                    kind,
+                    ..
+                },
+            ) => {
+                if kind == KeyEventKind::Press {
+                    if modifiers == CONTROL_KEY && self.handle_control_char(code)? {
+                        return Ok(());
                     }
-                    KeyCode::Backspace => {
-                        self.engine.send_text_input_backspace().ok();
+                    if code == KeyCode::Char('?') {
+                        self.terminal_window.toggle_show_help()?;
+                        return Ok(());
                     }
-                    _ => {}
                 }
-                Ok(())
+
+                self.send_key_event(key_event)
             }
             crossterm::event::Event::Mouse(MouseEvent {
                 kind,
@@ -85,81 +95,275 @@ impl TerminalEmbedder {
                         }
                         _ => (),
                     }
+                } else if row as usize >= self.dimensions.1 {
+                    // The mouse is over the logs below the app.
+                    self.remove_pointer()?;
                 } else {
-                    let (column, row) = (
+                    let position = (
                         column as f64 + self.window_offset.0 as f64,
                         row as f64 + self.window_offset.1 as f64,
                     );
                     match kind {
                         crossterm::event::MouseEventKind::Down(mouse_button) => {
-                            self.engine.send_pointer_event(
-                                FlutterPointerPhase::Down,
-                                (column as f64, row as f64),
-                                FlutterPointerSignalKind::None,
-                                0.0,
-                                vec![to_mouse_button(mouse_button)],
-                            )?;
+                            let phase = self.pointer.press(to_mouse_button(mouse_button));
+                            self.send_pointer_event(phase, position, None)?;
                         }
                         crossterm::event::MouseEventKind::Up(mouse_button) => {
-                            self.engine.send_pointer_event(
-                                FlutterPointerPhase::Up,
-                                (column as f64, row as f64),
-                                FlutterPointerSignalKind::None,
-                                0.0,
-                                vec![to_mouse_button(mouse_button)],
-                            )?;
+                            if let Some(phase) = self.pointer.release(to_mouse_button(mouse_button))
+                            {
+                                self.send_pointer_event(phase, position, None)?;
+                            }
                         }
-                        crossterm::event::MouseEventKind::Drag(_) => (),
-                        crossterm::event::MouseEventKind::Moved => {
-                            self.engine.send_pointer_event(
-                                FlutterPointerPhase::Hover,
-                                (column as f64, row as f64),
-                                FlutterPointerSignalKind::None,
-                                0.0,
-                                vec![],
-                            )?;
+                        crossterm::event::MouseEventKind::Drag(mouse_button) => {
+                            let phase = self.pointer.drag(to_mouse_button(mouse_button));
+                            self.send_pointer_event(phase, position, None)?;
                         }
-                        crossterm::event::MouseEventKind::ScrollUp => {
-                            self.engine.send_pointer_event(
-                                FlutterPointerPhase::Up,
-                                (column as f64, row as f64),
-                                FlutterPointerSignalKind::Scroll,
-                                -SCROLL_DELTA,
-                                vec![],
-                            )?;
+                        crossterm::event::MouseEventKind::Moved => {
+                            self.send_pointer_event(FlutterPointerPhase::Hover, position, None)?;
                         }
-                        crossterm::event::MouseEventKind::ScrollDown => {
-                            self.engine.send_pointer_event(
-                                FlutterPointerPhase::Down,
-                                (column as f64, row as f64),
-                                FlutterPointerSignalKind::Scroll,
-                                SCROLL_DELTA,
-                                vec![],
+                        crossterm::event::MouseEventKind::ScrollUp
+                        | crossterm::event::MouseEventKind::ScrollDown
+                        | crossterm::event::MouseEventKind::ScrollLeft
+                        | crossterm::event::MouseEventKind::ScrollRight => {
+                            let scroll_delta = match kind {
+                                MouseEventKind::ScrollUp => (0.0, -self.scroll_delta),
+                                MouseEventKind::ScrollDown => (0.0, self.scroll_delta),
+                                MouseEventKind::ScrollLeft => (-self.scroll_delta, 0.0),
+                                _ => (self.scroll_delta, 0.0),
+                            };
+                            self.send_pointer_event(
+                                self.pointer.signal_phase(),
+                                position,
+                                Some(scroll_delta),
                             )?;
                         }
-                        crossterm::event::MouseEventKind::ScrollLeft
-                        | crossterm::event::MouseEventKind::ScrollRight => {}
                     }
                 }
                 Ok(())
             }
-            crossterm::event::Event::Paste(_) => todo!(),
+            crossterm::event::Event::Paste(text) => {
+                self.text_input.paste(&self.engine, &text)?;
+                Ok(())
+            }
             crossterm::event::Event::Resize(columns, rows) => {
                 self.dimensions = (columns as usize, rows as usize);
-                self.terminal_window.mark_dirty();
+                self.terminal_window.resize();
                 self.engine.schedule_frame()?;
                 Ok(())
             }
         }
     }
 
-    fn handle_control_char(&mut self, code: KeyCode) -> Result<(), Error> {
+    /// Sends a mouse event to the framework, adding the pointer first if
+    /// needed.
+    ///
+    /// When `scroll_delta` is provided, this is a scroll signal.
+    fn send_pointer_event(
+        &mut self,
+        phase: FlutterPointerPhase,
+        position: (f64, f64),
+        scroll_delta: Option<(f64, f64)>,
+    ) -> Result<(), Error> {
+        if self.pointer.add(position) {
+            self.engine.send_pointer_event(
+                FlutterPointerPhase::Add,
+                position,
+                FlutterPointerSignalKind::None,
+                (0.0, 0.0),
+                vec![],
+            )?;
+        }
+        let (signal_kind, scroll_delta) = match scroll_delta {
+            Some(scroll_delta) => (FlutterPointerSignalKind::Scroll, scroll_delta),
+            None => (FlutterPointerSignalKind::None, (0.0, 0.0)),
+        };
+        self.engine.send_pointer_event(
+            phase,
+            position,
+            signal_kind,
+            scroll_delta,
+            self.pointer.buttons(),
+        )?;
+        Ok(())
+    }
+
+    /// Removes the pointer from the app, e.g. when the mouse leaves it, so
+    /// that hover effects end.
+    fn remove_pointer(&mut self) -> Result<(), Error> {
+        for phase in self.pointer.remove() {
+            self.engine.send_pointer_event(
+                phase,
+                self.pointer.position(),
+                FlutterPointerSignalKind::None,
+                (0.0, 0.0),
+                vec![],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Sends a terminal key event to the framework.
+    fn send_key_event(&mut self, event: KeyEvent) -> Result<(), Error> {
+        let Some(mut key) = keyboard::to_flutter_key(event.code) else {
+            return Ok(());
+        };
+        if event.modifiers.contains(CONTROL_KEY) {
+            // Control sequences don't produce text.
+            key.character = None;
+        }
+
+        if self.terminal_window.kitty_keyboard {
+            // Presses, repeats and releases of every key, including modifier
+            // keys, are reported by the terminal.
+            if let Some(event_type) = self.keyboard.update(&mut key, event.kind) {
+                let fallback = (event_type != FlutterKeyEventType::Up).then_some(event);
+                self.send_flutter_key(&key, event_type, event.modifiers, fallback)?;
+            }
+            return Ok(());
+        }
+
+        // Otherwise, terminals only report key presses, so the key is released
+        // right after it is pressed, and the modifiers are pressed and
+        // released around it.
+        let mut modifier_keys = keyboard::modifier_keys(event.modifiers);
+
+        for modifier_key in &mut modifier_keys {
+            if let Some(event_type) = self.keyboard.update(modifier_key, KeyEventKind::Press) {
+                self.send_flutter_key(modifier_key, event_type, event.modifiers, None)?;
+            }
+        }
+        if let Some(event_type) = self.keyboard.update(&mut key, KeyEventKind::Press) {
+            self.send_flutter_key(&key, event_type, event.modifiers, Some(event))?;
+        }
+        if let Some(event_type) = self.keyboard.update(&mut key, KeyEventKind::Release) {
+            self.send_flutter_key(&key, event_type, event.modifiers, None)?;
+        }
+        for modifier_key in modifier_keys.iter_mut().rev() {
+            if let Some(event_type) = self.keyboard.update(modifier_key, KeyEventKind::Release) {
+                self.send_flutter_key(modifier_key, event_type, event.modifiers, None)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Releases every key which the framework considers to be pressed, as the
+    /// terminal doesn't report releases while it isn't focused or the process
+    /// is stopped.
+    fn release_pressed_keys(&mut self) -> Result<(), Error> {
+        for key in self.keyboard.release_all() {
+            self.send_flutter_key(&key, FlutterKeyEventType::Up, KeyModifiers::NONE, None)?;
+        }
+        Ok(())
+    }
+
+    /// Sends a single key event to the framework.
+    ///
+    /// When `fallback` is provided and the framework does not handle the key,
+    /// it will be passed to [Self::handle_unhandled_key_event].
+    fn send_flutter_key(
+        &self,
+        key: &FlutterKey,
+        event_type: FlutterKeyEventType,
+        modifiers: KeyModifiers,
+        fallback: Option<KeyEvent>,
+    ) -> Result<(), Error> {
+        let is_down = event_type != FlutterKeyEventType::Up;
+
+        self.engine.send_key_event(
+            event_type,
+            key.physical,
+            key.logical,
+            if is_down {
+                key.character.as_deref()
+            } else {
+                None
+            },
+            false,
+        )?;
+
+        // The framework only dispatches key data after the raw key message
+        // arrives, and only the raw key message reports whether the event was
+        // handled.
+        let message = key.raw_key_message(is_down, modifiers);
+        match fallback {
+            Some(event) => {
+                let sender = self.platform_events_sender.clone();
+                self.engine.send_platform_message_with_reply(
+                    KEY_EVENT_CHANNEL,
+                    &message,
+                    Box::new(move |response| {
+                        if !keyboard::is_handled(response) {
+                            sender
+                                .send(PlatformEvent::EngineEvent(EngineEvent::KeyEventUnhandled(
+                                    event,
+                                )))
+                                .unwrap();
+                        }
+                    }),
+                )?;
+            }
+            None => {
+                self.engine
+                    .send_platform_message(KEY_EVENT_CHANNEL, &message)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles key events which were not handled by the framework, by passing
+    /// them to the focused text field.
+    pub(crate) fn handle_unhandled_key_event(&mut self, event: KeyEvent) -> Result<(), Error> {
+        let key = match event.code {
+            KeyCode::Char(c) if !event.modifiers.contains(CONTROL_KEY) => {
+                self.text_input
+                    .insert_text(&self.engine, c.encode_utf8(&mut [0; 4]))
+                    .ok();
+                return Ok(());
+            }
+            KeyCode::Backspace => TextInputKey::Backspace,
+            KeyCode::Delete => TextInputKey::Delete,
+            KeyCode::Left => TextInputKey::Left,
+            KeyCode::Right => TextInputKey::Right,
+            KeyCode::Home => TextInputKey::Home,
+            KeyCode::End => TextInputKey::End,
+            KeyCode::Enter => TextInputKey::Enter,
+            _ => return Ok(()),
+        };
+        let extend_selection = event.modifiers.contains(KeyModifiers::SHIFT);
+        self.text_input
+            .handle_key(&self.engine, key, extend_selection)
+            .ok();
+        Ok(())
+    }
+
+    /// Pauses the app, then suspends the process with
+    /// [PlatformEvent::ReadyToSuspend] once the framework has handled it, as
+    /// it can't while the process is stopped.
+    pub(crate) fn request_suspend(&mut self) -> Result<(), Error> {
+        self.release_pressed_keys()?;
+
+        let sender = self.platform_events_sender.clone();
+        send_lifecycle_state_with_reply(
+            &self.engine,
+            AppLifecycleState::Paused,
+            Box::new(move || sender.send(PlatformEvent::ReadyToSuspend).unwrap()),
+        )?;
+        Ok(())
+    }
+
+    /// Handles shortcuts of the embedder, returning whether `code` was one of
+    /// them.
+    fn handle_control_char(&mut self, code: KeyCode) -> Result<bool, Error> {
         match code {
             KeyCode::Char('c') => {
                 self.should_run = false;
-                Ok(())
+                Ok(true)
             }
             KeyCode::Char('z') => {
+                self.request_suspend()?;
+                Ok(true)
+            }
+            KeyCode::Char('t') => {
                 self.show_semantics = !self.show_semantics;
                 // Flutter does not update the semantics callback when they are disabled.
                 if !self.show_semantics {
@@ -167,23 +371,23 @@ impl TerminalEmbedder {
                     self.terminal_window.mark_dirty();
                 }
                 self.engine.update_semantics(self.show_semantics)?;
-                Ok(())
+                Ok(true)
             }
             KeyCode::Char('r') => {
                 self.reset_viewport()?;
-                Ok(())
+                Ok(true)
             }
             KeyCode::Char('5') => {
                 self.scale *= ZOOM_FACTOR;
                 self.engine.schedule_frame()?;
-                Ok(())
+                Ok(true)
             }
             KeyCode::Char('4') => {
                 self.scale /= ZOOM_FACTOR;
                 self.engine.schedule_frame()?;
-                Ok(())
+                Ok(true)
             }
-            _ => Ok(()),
+            _ => Ok(false),
         }
     }
 }
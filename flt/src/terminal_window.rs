@@ -1,33 +1,44 @@
-//! This should be the only file in this crate which depends on [crossterm]
+//! This, [crate::render_thread] and the [crate::renderer] implementations
+//! should be the only files in this crate which depend on [crossterm]
 //! functionality beyond the data classes.
 
+use crate::ansi::AnsiGlyphs;
+use crate::color::{ColorDepth, Dithering, Rgb};
 use crate::event::PlatformEvent;
+use crate::feature::ClipboardReply;
+use crate::render_thread::{RenderJob, RenderThread};
+use crate::renderer::{RendererConfig, RendererKind};
 use base64::prelude::*;
 use crossterm::cursor::{Hide, MoveTo, Show};
-use crossterm::event::{read, DisableMouseCapture, EnableMouseCapture, Event};
-use crossterm::style::{Color, Print, PrintStyledContent, Stylize};
+use crossterm::event::{
+    poll, read, DisableBracketedPaste, DisableFocusChange, DisableMouseCapture,
+    EnableBracketedPaste, EnableFocusChange, EnableMouseCapture, Event, KeyboardEnhancementFlags,
+    PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+};
+use crossterm::style::Print;
 use crossterm::terminal::{
     self, disable_raw_mode, enable_raw_mode, window_size, Clear, ClearType, EnterAlternateScreen,
     LeaveAlternateScreen,
 };
 use crossterm::{ExecutableCommand, QueueableCommand};
-use libc::{ftruncate, shm_open, shm_unlink, O_CREAT, O_RDWR, O_TRUNC};
-use memmap2::MmapMut;
 use std::collections::{HashMap, VecDeque};
 use std::fs::OpenOptions;
 use std::io::{stdout, Stdout, Write};
-use std::iter::zip;
-use std::os::unix::io::FromRawFd;
 use std::sync::mpsc::Sender;
+use std::sync::{Arc, Condvar, Mutex};
 use std::thread;
-use std::time::Instant;
+use std::time::Duration;
 
 /// Lines to reserve the terminal for logging.
 const LOGGING_WINDOW_HEIGHT: usize = 4;
 
 pub struct TerminalWindow {
     stdout: Stdout,
-    lines: Vec<Vec<TerminalCell>>,
+    render_thread: RenderThread,
+    /// Whether the renderer draws in pixels, as opposed to in cells.
+    draws_pixels: bool,
+    /// How long the last frame took to draw.
+    frame_duration: Duration,
     logs: VecDeque<String>,
     log_file_writer: Option<std::fs::File>,
     // Coordinates of semantics is represented in the "external" height.
@@ -39,86 +50,77 @@ pub struct TerminalWindow {
     alternate_screen: bool,
     showing_help: bool,
     pub(crate) log_events: bool,
-    kitty_mode: bool,
+    /// Whether the kitty keyboard protocol is enabled, so that key releases,
+    /// repeats and modifier keys are reported.
+    pub(crate) kitty_keyboard: bool,
+    /// Whether mouse events are reported in pixels, with the SGR-Pixels mode.
+    sgr_pixels: bool,
+    /// Whether the mouse pointer shape can be set with OSC 22.
+    pointer_shape_supported: bool,
+    /// The background color of the terminal, or black if it can't be queried.
+    background: Rgb,
     pixels_per_col: f64,
     pixels_per_row: f64,
     device_pixel_ratio: f64,
-    shm_buffer: Option<SharedMemoryBuffer>,
-    frame_count: u64,
     logs_dirty: bool,
+    input_pause: Arc<InputPause>,
+    /// Whether the process was suspended with [Self::suspend], until it is
+    /// [Self::continued].
+    suspended: bool,
 }
 
-struct SharedMemoryBuffer {
-    name: String,
-    // We keep file to keep the FD open (though shm persists until unlinked/closed)
-    // and to allow resizing (if we used File methods).
-    // But we used libc::shm_open which returns raw fd.
-    // We can wrap it in ManuallyDrop<File> or just raw fd.
-    // memmap2 `map_mut` takes &File.
-    #[allow(dead_code)]
-    file: std::fs::File,
-    map: Option<MmapMut>,
-}
-
-impl SharedMemoryBuffer {
-    fn new(size: usize, suffix: u64) -> std::io::Result<Self> {
-        let pid = std::process::id();
-        let name = format!("/flt_{}_{}", pid, suffix);
-        let c_name = std::ffi::CString::new(name.clone())?;
+/// How often the input thread checks if it should pause.
+const INPUT_POLL_INTERVAL: Duration = Duration::from_millis(50);
 
-        // Ensure clean state
-        unsafe { shm_unlink(c_name.as_ptr()) };
+/// Allows the terminal to be queried after the input thread has started, by
+/// pausing the thread so that it doesn't consume the reply.
+#[derive(Default)]
+struct InputPause {
+    /// Whether a pause is requested, and whether the input thread is paused.
+    state: Mutex<(bool, bool)>,
+    condvar: Condvar,
+}
 
-        let fd = unsafe { shm_open(c_name.as_ptr(), O_RDWR | O_CREAT | O_TRUNC, 0o600) };
-        if fd < 0 {
-            return Err(std::io::Error::last_os_error());
+impl InputPause {
+    /// Called by the input thread before reading, to block while a pause is
+    /// requested.
+    fn wait_if_requested(&self) {
+        let mut state = self.state.lock().unwrap();
+        if !state.0 {
+            return;
         }
-
-        let file = unsafe { std::fs::File::from_raw_fd(fd) };
-
-        if unsafe { ftruncate(fd, size as i64) } < 0 {
-            return Err(std::io::Error::last_os_error());
+        state.1 = true;
+        self.condvar.notify_all();
+        while state.0 {
+            state = self.condvar.wait(state).unwrap();
         }
-
-        let map = unsafe { MmapMut::map_mut(&file)? };
-
-        Ok(Self {
-            name,
-            file,
-            map: Some(map),
-        })
+        state.1 = false;
     }
-}
 
-impl Drop for SharedMemoryBuffer {
-    fn drop(&mut self) {
-        if let Ok(c_name) = std::ffi::CString::new(self.name.clone()) {
-            unsafe { shm_unlink(c_name.as_ptr()) };
-        }
+    /// Runs `f` while the input thread is paused, or returns `None` if the
+    /// thread did not pause in time.
+    fn run<T>(&self, f: impl FnOnce() -> T) -> Option<T> {
+        let mut state = self.state.lock().unwrap();
+        state.0 = true;
+        let (state, _) = self
+            .condvar
+            .wait_timeout_while(state, INPUT_POLL_INTERVAL * 2, |state| !state.1)
+            .unwrap();
+        let paused = state.1;
+        drop(state);
+
+        let result = paused.then(f);
+
+        self.state.lock().unwrap().0 = false;
+        self.condvar.notify_all();
+        result
     }
 }
 
 impl Drop for TerminalWindow {
     fn drop(&mut self) {
         if !self.simple_output {
-            if self.kitty_mode {
-                // On iTerm2, kitty graphics need to be explicitly cleared, otherwise the last frame will persist on the screen.
-                // \x1b_G: Start of Kitty graphics command
-                // a=d: Action = delete
-                // d=a: Delete = all
-                // \x1b\\: String terminator
-                let _ = self.stdout.execute(Print("\x1b_Ga=d,d=a\x1b\\"));
-            }
-
-            self.stdout.execute(DisableMouseCapture).unwrap();
-            disable_raw_mode().unwrap();
-
-            // Show cursor.
-            self.stdout.execute(Show).unwrap();
-
-            if self.alternate_screen {
-                self.stdout.execute(LeaveAlternateScreen).unwrap();
-            }
+            self.leave_terminal().unwrap();
             // Add a newline char so any other subsequent logs appear on the next line.
             self.stdout.execute(Print("\n")).unwrap();
         }
@@ -131,31 +133,49 @@ impl TerminalWindow {
         alternate_screen: bool,
         log_events: bool,
         disable_kitty: bool,
+        renderer: Option<RendererKind>,
+        ansi_glyphs: AnsiGlyphs,
+        color_depth: Option<ColorDepth>,
+        dithering: Dithering,
         event_sender: Sender<PlatformEvent>,
         log_file: Option<String>,
     ) -> Self {
         let mut stdout = stdout();
 
         if !simple_output {
-            if alternate_screen {
-                // This causes the terminal to be output on an alternate buffer.
-                stdout.execute(EnterAlternateScreen).unwrap();
-            }
-
-            // Hide cursor.
-            stdout.execute(Hide).unwrap();
-
+            // Needed to read the replies of the feature queries below.
             enable_raw_mode().unwrap();
-            stdout.execute(EnableMouseCapture).unwrap();
         }
 
-        let kitty_mode = if !simple_output && !disable_kitty {
-            crate::feature::kitty_graphics_supported(&mut stdout)
+        let renderer = if simple_output {
+            RendererConfig::Ansi
         } else {
-            false
-        };
+            RendererKind::detect(renderer, &mut stdout, disable_kitty)
+        }
+        .create(
+            ansi_glyphs,
+            color_depth.unwrap_or_else(ColorDepth::detect),
+            dithering,
+        );
 
-        let (pixels_per_col, pixels_per_row) = if kitty_mode {
+        let kitty_keyboard =
+            !simple_output && crate::feature::kitty_keyboard_supported(&mut stdout);
+        let pointer_shape_supported =
+            !simple_output && crate::feature::pointer_shape_supported(&mut stdout);
+        // Only used with graphics, where the app is drawn in pixels. Otherwise,
+        // a cell is already the smallest unit the app is drawn in.
+        let sgr_pixels =
+            renderer.draws_pixels() && crate::feature::sgr_pixels_supported(&mut stdout);
+        let synchronized_output =
+            !simple_output && crate::feature::synchronized_output_supported(&mut stdout);
+        let background = if simple_output {
+            None
+        } else {
+            crate::feature::query_background_color(&mut stdout)
+        }
+        .unwrap_or((0, 0, 0));
+
+        let (pixels_per_col, pixels_per_row) = if renderer.draws_pixels() {
             match window_size() {
                 Ok(crossterm::terminal::WindowSize {
                     width: w_px,
@@ -168,32 +188,55 @@ impl TerminalWindow {
                 _ => (10.0, 20.0), // Fallback to a high-res default
             }
         } else {
-            (1.0, 2.0)
+            let (columns, rows) = ansi_glyphs.cell_pixels();
+            (columns as f64, rows as f64)
         };
 
-        let device_pixel_ratio = if kitty_mode {
+        let device_pixel_ratio = if renderer.draws_pixels() {
             pixels_per_row.max(1.0) / 22.0
         } else {
-            crate::constants::DEFAULT_PIXEL_RATIO
+            // Scale with the glyphs so that the app is the same size in cells.
+            crate::constants::DEFAULT_PIXEL_RATIO * pixels_per_row / 2.0
         };
 
+        let input_pause = Arc::new(InputPause::default());
+        let thread_input_pause = input_pause.clone();
+        let input_event_sender = event_sender.clone();
+
         thread::spawn(move || {
             let mut should_run = true;
             while should_run {
+                thread_input_pause.wait_if_requested();
+
+                // Poll instead of blocking on `read` so that the thread can be
+                // paused.
+                if !poll(INPUT_POLL_INTERVAL).unwrap() {
+                    continue;
+                }
                 let event = read().unwrap();
-                let event = normalize_event_height(event, pixels_per_col, pixels_per_row);
-                should_run = event_sender
+                let event =
+                    normalize_event_height(event, pixels_per_col, pixels_per_row, sgr_pixels);
+                should_run = input_event_sender
                     .send(PlatformEvent::TerminalEvent(event))
                     .is_ok();
             }
         });
 
+        if !simple_output {
+            crate::signals::forward_signals(event_sender.clone()).unwrap();
+        }
+
         let log_file_writer =
             log_file.and_then(|path| OpenOptions::new().create(true).append(true).open(path).ok());
 
-        Self {
+        let draws_pixels = renderer.draws_pixels();
+        let render_thread = RenderThread::new(renderer, synchronized_output, event_sender);
+
+        let mut terminal_window = Self {
             stdout,
-            lines: vec![],
+            render_thread,
+            draws_pixels,
+            frame_duration: Duration::ZERO,
             logs: VecDeque::new(),
             log_file_writer,
             semantics: HashMap::new(),
@@ -201,14 +244,121 @@ impl TerminalWindow {
             showing_help: false,
             alternate_screen,
             log_events,
-            kitty_mode,
+            kitty_keyboard,
+            sgr_pixels,
+            pointer_shape_supported,
+            background,
             pixels_per_col,
             pixels_per_row,
             device_pixel_ratio,
-            shm_buffer: None,
-            frame_count: 0,
             logs_dirty: true,
+            input_pause,
+            suspended: false,
+        };
+
+        if !simple_output {
+            terminal_window.enter_terminal().unwrap();
         }
+        terminal_window.resize();
+
+        terminal_window
+    }
+
+    /// Sets up the terminal for drawing and reading input.
+    fn enter_terminal(&mut self) -> Result<(), std::io::Error> {
+        if self.alternate_screen {
+            // This causes the terminal to be output on an alternate buffer.
+            self.stdout.execute(EnterAlternateScreen)?;
+        }
+
+        // Hide cursor.
+        self.stdout.execute(Hide)?;
+
+        enable_raw_mode()?;
+        self.stdout.execute(EnableMouseCapture)?;
+        if self.sgr_pixels {
+            // \x1b[?1016h : Enable the SGR-Pixels mouse mode.
+            self.stdout.execute(Print("\x1b[?1016h"))?;
+        }
+        self.stdout.execute(EnableBracketedPaste)?;
+        self.stdout.execute(EnableFocusChange)?;
+
+        if self.kitty_keyboard {
+            self.stdout.execute(PushKeyboardEnhancementFlags(
+                KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES
+                    | KeyboardEnhancementFlags::REPORT_EVENT_TYPES
+                    | KeyboardEnhancementFlags::REPORT_ALL_KEYS_AS_ESCAPE_CODES
+                    // Needed for the shifted character of keys to be
+                    // reported, e.g. `!` instead of shift + `1`.
+                    | KeyboardEnhancementFlags::REPORT_ALTERNATE_KEYS,
+            ))?;
+        }
+        Ok(())
+    }
+
+    /// Restores the terminal to the state before [Self::enter_terminal].
+    fn leave_terminal(&mut self) -> Result<(), std::io::Error> {
+        self.render_thread.discard_pending();
+        self.render_thread.renderer().teardown(&mut self.stdout)?;
+
+        if self.kitty_keyboard {
+            self.stdout.execute(PopKeyboardEnhancementFlags)?;
+        }
+        self.set_pointer_shape("default")?;
+        self.stdout.execute(DisableFocusChange)?;
+        self.stdout.execute(DisableBracketedPaste)?;
+        if self.sgr_pixels {
+            // \x1b[?1016l : Disable the SGR-Pixels mouse mode.
+            self.stdout.execute(Print("\x1b[?1016l"))?;
+        }
+        self.stdout.execute(DisableMouseCapture)?;
+        disable_raw_mode()?;
+
+        // Show cursor.
+        self.stdout.execute(Show)?;
+
+        if self.alternate_screen {
+            self.stdout.execute(LeaveAlternateScreen)?;
+        }
+        Ok(())
+    }
+
+    /// Suspends the process like Ctrl + z does in a shell, returning after it
+    /// is continued (e.g. with `fg`).
+    pub(crate) fn suspend(&mut self) -> Result<(), std::io::Error> {
+        if self.simple_output {
+            return Ok(());
+        }
+
+        self.leave_terminal()?;
+        // Raw mode disables the signal that the terminal would have sent for
+        // Ctrl + z, so send it ourselves.
+        crate::signals::stop_process_group();
+        // The terminal is restored below, instead of on the SIGCONT which
+        // continued the process.
+        self.suspended = true;
+        self.restore_terminal()
+    }
+
+    /// Called when the process was continued after being stopped.
+    pub(crate) fn continued(&mut self) -> Result<(), std::io::Error> {
+        if self.simple_output || std::mem::take(&mut self.suspended) {
+            return Ok(());
+        }
+
+        // The process was stopped from outside, e.g. with SIGSTOP, without
+        // leaving the terminal. The shell might have reset it since.
+        self.leave_terminal()?;
+        self.restore_terminal()
+    }
+
+    fn restore_terminal(&mut self) -> Result<(), std::io::Error> {
+        self.enter_terminal()?;
+
+        // The shell might have drawn over the screen.
+        self.stdout.execute(Clear(ClearType::All))?;
+        self.mark_dirty();
+        Ok(())
     }
 
     pub(crate) fn device_pixel_ratio(&self) -> f64 {
@@ -216,8 +366,8 @@ impl TerminalWindow {
     }
 
     pub(crate) fn size(&self) -> (usize, usize) {
-        if self.kitty_mode {
-            // In kitty mode, we need precise pixel dimensions to properly align the image
+        if self.draws_pixels {
+            // With graphics, we need precise pixel dimensions to properly align the image
             // with the terminal grid. Standard `terminal::size()` only gives character dimensions.
             if let Ok(terminal::WindowSize {
                 width: w_px,
@@ -278,166 +428,72 @@ impl TerminalWindow {
             return Ok(());
         }
 
-        let terminal_size = terminal::size()?;
-        let start_instant = Instant::now();
-
-        if self.kitty_mode {
-            self.draw_kitty(buffer, width, height)?;
-        } else {
-            // TODO(jiahaog): Put this into a function called `draw_ansi`.
-
-            let (cell_cols, cell_rows) = terminal_size;
-            let cell_rows = cell_rows as usize - LOGGING_WINDOW_HEIGHT;
-            let cell_cols = cell_cols as usize;
-
-            let mut lines = Vec::with_capacity(cell_rows);
-
-            for y in (0..cell_rows).step_by(1) {
-                let mut row_cells = Vec::with_capacity(cell_cols);
-
-                for x in 0..cell_cols {
-                    let pixel_x = x_offset + x as isize;
-                    let pixel_y_top = y_offset + (y * 2) as isize;
-                    let pixel_y_bot = y_offset + (y * 2 + 1) as isize;
-
-                    let top_pixel = get_pixel(&buffer, width, height, pixel_x, pixel_y_top);
-                    let bot_pixel = get_pixel(&buffer, width, height, pixel_x, pixel_y_bot);
-
-                    let semantics = None;
-
-                    row_cells.push(TerminalCell {
-                        top: to_color_from_bytes(top_pixel),
-                        bottom: to_color_from_bytes(bot_pixel),
-                        semantics,
-                    });
-                }
-                lines.push(row_cells);
-            }
-
-            if self.lines.len() != lines.len() {
-                self.lines = vec![vec![]; lines.len()];
-            }
+        assert!(self.logs.len() <= LOGGING_WINDOW_HEIGHT);
+        let mut logs: Vec<_> = self.logs.iter().cloned().collect();
+        logs.resize(LOGGING_WINDOW_HEIGHT, String::new());
 
-            for (y, (prev, current)) in zip(&self.lines, &lines).enumerate() {
-                for (
-                    x,
-                    current_cell @ TerminalCell {
-                        top,
-                        bottom,
-                        semantics: _,
-                    },
-                ) in current.into_iter().enumerate()
-                {
-                    if prev
-                        .get(x)
-                        .filter(|prev_cell| prev_cell == &current_cell)
-                        .is_some()
-                    {
-                        continue;
-                    }
-                    self.stdout.queue(MoveTo(x as u16, y as u16))?;
-                    self.stdout.queue(PrintStyledContent(
-                        BLOCK_UPPER.to_string().with(*top).on(*bottom),
-                    ))?;
-                }
-            }
+        self.render_thread.submit(RenderJob {
+            buffer,
+            width,
+            height,
+            offset: (x_offset, y_offset),
+            background: self.background,
+            terminal_size: terminal::size()?,
+            logs,
+            logs_dirty: self.logs_dirty,
+            status: format!("{HELP_HINT} [{}ms]", self.frame_duration.as_millis()),
+        });
+        self.logs_dirty = false;
 
-            self.lines = lines;
-        }
+        Ok(())
+    }
 
-        {
-            assert!(self.logs.len() <= LOGGING_WINDOW_HEIGHT);
-
-            let (cols, rows) = terminal_size;
-            let cols = cols as usize;
-            let rows = rows as usize;
-
-            if rows >= LOGGING_WINDOW_HEIGHT {
-                for i in 0..LOGGING_WINDOW_HEIGHT {
-                    let is_last_line = i == LOGGING_WINDOW_HEIGHT - 1;
-                    if !self.logs_dirty && !is_last_line {
-                        continue;
-                    }
-
-                    let y = rows - LOGGING_WINDOW_HEIGHT + i;
-                    self.stdout.queue(MoveTo(0, y as u16))?;
-                    self.stdout
-                        .queue(Clear(crossterm::terminal::ClearType::CurrentLine))?;
-
-                    if let Some(line) = self.logs.get(i) {
-                        self.stdout.queue(Print(line))?;
-                    }
-
-                    if is_last_line {
-                        let draw_duration = Instant::now().duration_since(start_instant);
-                        let hint_and_fps = format!("{HELP_HINT} [{}ms]", draw_duration.as_millis());
-                        self.stdout.queue(MoveTo(
-                            (cols.saturating_sub(hint_and_fps.len())) as u16,
-                            y as u16,
-                        ))?;
-                        self.stdout.queue(Print(hint_and_fps))?;
-                    }
-                }
-            }
+    /// Called when the render thread finished drawing a frame.
+    pub(crate) fn frame_drawn(&mut self, duration: Duration) {
+        self.frame_duration = duration;
+    }
 
-            self.logs_dirty = false;
+    /// Sets the shape of the mouse pointer with OSC 22, where `shape` is a CSS
+    /// cursor name, e.g. `pointer`.
+    pub(crate) fn set_pointer_shape(&mut self, shape: &str) -> Result<(), std::io::Error> {
+        if !self.pointer_shape_supported {
+            return Ok(());
         }
 
-        self.stdout.flush()?;
-
+        // \x1b]22;{shape}\x1b\\ : Set the pointer shape.
+        self.stdout
+            .execute(Print(format!("\x1b]22;{}\x1b\\", shape)))?;
         Ok(())
     }
 
-    fn draw_kitty(
-        &mut self,
-        buffer: Vec<u8>,
-        width: usize,
-        height: usize,
-    ) -> Result<(), std::io::Error> {
-        if buffer.is_empty() {
+    /// Sets the system clipboard with OSC 52.
+    pub(crate) fn write_clipboard(&mut self, text: &str) -> Result<(), std::io::Error> {
+        if self.simple_output {
             return Ok(());
         }
 
-        // Generate unique SHM segment for this frame
-        self.frame_count += 1;
-        let needed_size = buffer.len();
-
-        let mut new_shm = SharedMemoryBuffer::new(needed_size, self.frame_count)?;
+        // \x1b]52;c;{base64 contents}\x1b\\ : Set the clipboard.
+        self.stdout.execute(Print(format!(
+            "\x1b]52;c;{}\x1b\\",
+            BASE64_STANDARD.encode(text)
+        )))?;
+        Ok(())
+    }
 
-        // Write to SHM
-        if let Some(map) = &mut new_shm.map {
-            map[0..needed_size].copy_from_slice(&buffer);
-            let _ = map.flush();
+    /// Reads the system clipboard with OSC 52.
+    ///
+    /// This blocks for up to [crate::feature::CLIPBOARD_TIMEOUT] when the
+    /// terminal doesn't reply.
+    pub(crate) fn read_clipboard(&mut self) -> ClipboardReply {
+        if self.simple_output {
+            return ClipboardReply::Unsupported;
         }
 
-        // Send Command
-        self.stdout.queue(MoveTo(0, 0))?;
-
-        // Initialize transfer.
-        // f=32: 32-bit RGBA
-        // s={pixel_width},v={pixel_height}: dimensions
-        // a=T: transmit and display
-        // q=2: quiet mode (no response)
-        // i=1: image ID for overwriting
-        // p=1 (Constant Placement ID to avoid leaking placements)
-        // z=1: z-index (positive = above text)
-        // C=1: do not move cursor
-        // t=s (Shared Memory)
-        // Payload is the encoded name of the NEW SHM segment
-        let code = format!(
-            "\x1b_Gf=32,s={},v={},a=T,q=2,i=1,p=1,z=1,C=1,t=s;{}\x1b\\",
-            width,
-            height,
-            BASE64_STANDARD.encode(&new_shm.name)
-        );
-        self.stdout.queue(Print(code))?;
-        self.stdout.flush()?;
-
-        // Store the new SHM buffer.
-        // This drops the previous one (if any), which triggers shm_unlink.
-        self.shm_buffer = Some(new_shm);
-
-        Ok(())
+        let stdout = &mut self.stdout;
+        self.input_pause
+            .run(|| crate::feature::query_clipboard(stdout))
+            // The input thread is busy, which says nothing about the terminal.
+            .unwrap_or(ClipboardReply::Invalid)
     }
 
     pub(crate) fn log(&mut self, message: String) {
@@ -465,6 +521,8 @@ impl TerminalWindow {
 
     pub(crate) fn toggle_show_help(&mut self) -> Result<(), std::io::Error> {
         self.showing_help = !self.showing_help;
+        // Otherwise it might be drawn over the help.
+        self.render_thread.discard_pending();
 
         self.stdout.execute(Clear(ClearType::All))?;
         self.mark_dirty();
@@ -486,14 +544,16 @@ impl TerminalWindow {
                 .queue(Print("Ctrl + Mouse Click and Drag: Pan the viewport. Some terminals might not allow this."))?;
             self.stdout.queue(MoveTo(0, 10))?;
             self.stdout.queue(Print(
-                "Ctrl + z: Show semantic labels (very experimental and jank).",
+                "Ctrl + t: Show semantic labels (very experimental and jank).",
             ))?;
             self.stdout.queue(MoveTo(0, 12))?;
+            self.stdout.queue(Print("Ctrl + z: Suspend."))?;
+            self.stdout.queue(MoveTo(0, 14))?;
             self.stdout.queue(Print("?: Toggle help."))?;
 
-            self.stdout.queue(MoveTo(0, 14))?;
+            self.stdout.queue(MoveTo(0, 16))?;
             self.stdout.queue(Print("Tips: Changing the current terminal emulator's text size will make things look a lot better. "))?;
-            self.stdout.queue(MoveTo(0, 15))?;
+            self.stdout.queue(MoveTo(0, 17))?;
             self.stdout.queue(Print(
                 "But the code is suboptimal and it might lead to more jank.",
             ))?;
@@ -502,22 +562,26 @@ impl TerminalWindow {
         Ok(())
     }
 
+    /// Called when the terminal is resized.
+    pub(crate) fn resize(&mut self) {
+        if let Ok((columns, rows)) = terminal::size() {
+            let rows = (rows as usize).saturating_sub(LOGGING_WINDOW_HEIGHT);
+            self.render_thread.resize(columns as usize, rows);
+        }
+        self.mark_dirty();
+    }
+
     pub(crate) fn mark_dirty(&mut self) {
-        self.lines.clear();
+        self.render_thread.clear();
         self.logs_dirty = true;
     }
 }
 
-#[derive(PartialEq, Eq, Clone)]
-struct TerminalCell {
-    top: Color,
-    bottom: Color,
-    semantics: Option<String>,
-}
-
-const BLOCK_UPPER: char = '▀';
-
-fn normalize_event_height(event: Event, x_scale: f64, y_scale: f64) -> Event {
+/// Converts the dimensions of terminal events from cells into the units the
+/// app is drawn in.
+///
+/// Mouse events are already in pixels when `pixel_mouse` is set.
+fn normalize_event_height(event: Event, x_scale: f64, y_scale: f64, pixel_mouse: bool) -> Event {
     match event {
         Event::Resize(columns, rows) => {
             let rows = rows - LOGGING_WINDOW_HEIGHT as u16;
@@ -526,7 +590,7 @@ fn normalize_event_height(event: Event, x_scale: f64, y_scale: f64) -> Event {
                 (rows as f64 * y_scale).round() as u16,
             )
         }
-        Event::Mouse(mut mouse_event) => {
+        Event::Mouse(mut mouse_event) if !pixel_mouse => {
             mouse_event.column = (mouse_event.column as f64 * x_scale).round() as u16;
             mouse_event.row = (mouse_event.row as f64 * y_scale).round() as u16;
             Event::Mouse(mouse_event)
@@ -535,40 +599,4 @@ fn normalize_event_height(event: Event, x_scale: f64, y_scale: f64) -> Event {
     }
 }
 
-// Helper to get pixel from flat buffer safely
-fn get_pixel(buffer: &[u8], width: usize, height: usize, x: isize, y: isize) -> Option<&[u8]> {
-    if x < 0 || y < 0 || x >= width as isize || y >= height as isize {
-        return None;
-    }
-    let idx = (y as usize * width + x as usize) * 4;
-    if idx + 4 <= buffer.len() {
-        Some(&buffer[idx..idx + 4])
-    } else {
-        None
-    }
-}
-
-fn to_color_from_bytes(pixel_bytes: Option<&[u8]>) -> Color {
-    match pixel_bytes {
-        Some(slice) => {
-            // Assume engine format matches platform (BGRA on Linux, RGBA on Mac)
-            // But we want Color::Rgb which is r, g, b.
-            if cfg!(target_os = "macos") {
-                Color::Rgb {
-                    r: slice[0],
-                    g: slice[1],
-                    b: slice[2],
-                }
-            } else {
-                Color::Rgb {
-                    r: slice[2],
-                    g: slice[1],
-                    b: slice[0],
-                }
-            }
-        }
-        None => Color::Rgb { r: 0, g: 0, b: 0 },
-    }
-}
-
 const HELP_HINT: &str = "? for help";
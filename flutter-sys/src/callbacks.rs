@@ -21,6 +21,17 @@ pub struct PlatformMessage {
     pub response_handle: PlatformMessageResponseHandle,
 }
 
+/// Identifies a request for a vsync from the engine, which is passed back with
+/// [crate::FlutterEngine::on_vsync].
+#[derive(Debug)]
+pub struct VsyncBaton(pub(crate) isize);
+
+impl VsyncBaton {
+    pub fn new(baton: isize) -> Self {
+        Self(baton)
+    }
+}
+
 pub struct Callbacks {
     pub post_platform_task_callback: Option<Box<dyn Fn(EngineTask) -> ()>>,
     pub platform_task_runs_task_on_current_thread_callback: Option<Box<dyn Fn() -> bool>>,
@@ -28,7 +39,12 @@ pub struct Callbacks {
     pub update_semantics_callback: Option<Box<dyn Fn(Vec<SemanticsUpdate>) -> ()>>,
     pub draw_callback: Option<Box<dyn Fn(&[u8], usize, usize) -> ()>>,
     pub platform_message_callback: Option<Box<dyn Fn(PlatformMessage) -> ()>>,
+    pub on_pre_engine_restart_callback: Option<Box<dyn Fn() -> ()>>,
     pub get_next_drawable_callback:
         Option<Box<dyn Fn(sys::FlutterFrameInfo) -> sys::FlutterMetalTexture>>,
     pub present_drawable_callback: Option<Box<dyn Fn(&sys::FlutterMetalTexture) -> bool>>,
+    /// When set, the engine waits for [crate::FlutterEngine::on_vsync] before
+    /// producing each frame, instead of assuming the refresh rate of the
+    /// display.
+    pub vsync_callback: Option<Box<dyn Fn(VsyncBaton) -> ()>>,
 }
@@ -1,7 +1,8 @@
+use crate::key_event::FlutterKeyEventType;
 use crate::pointer::{FlutterPointerMouseButton, FlutterPointerPhase, FlutterPointerSignalKind};
 use crate::project_args::FlutterProjectArgs;
 use crate::user_data::UserData;
-use crate::{sys, Callbacks, Error};
+use crate::{sys, Callbacks, Error, VsyncBaton};
 use std::ffi::CString;
 use std::slice;
 use std::time::{Duration, Instant};
@@ -63,12 +64,13 @@ impl FlutterEngine {
                 }
             };
 
+        let vsync = callbacks.vsync_callback.is_some();
         let mut user_data = Box::new(UserData::new(callbacks));
 
         let user_data_ptr: *mut UserData = &mut *user_data;
         let user_data_ptr: *mut std::ffi::c_void = user_data_ptr as *mut std::ffi::c_void;
 
-        let project_args = FlutterProjectArgs::new(assets_dir, icu_data_path, user_data_ptr);
+        let project_args = FlutterProjectArgs::new(assets_dir, icu_data_path, user_data_ptr, vsync);
 
         let mut engine = Self {
             engine: std::ptr::null_mut(),
@@ -143,6 +145,29 @@ impl FlutterEngine {
         }
     }
 
+    /// Replies to the request for a vsync identified by `baton`, allowing the
+    /// engine to produce a frame which should be shown by
+    /// `frame_target_time_nanos`, on the clock of [crate::current_time_nanos].
+    pub fn on_vsync(
+        &self,
+        baton: VsyncBaton,
+        frame_start_time_nanos: u64,
+        frame_target_time_nanos: u64,
+    ) -> Result<(), Error> {
+        let result = unsafe {
+            sys::FlutterEngineOnVsync(
+                self.get_engine(),
+                baton.0,
+                frame_start_time_nanos,
+                frame_target_time_nanos,
+            )
+        };
+        match result {
+            sys::FlutterEngineResult_kSuccess => Ok(()),
+            err => Err(err.into()),
+        }
+    }
+
     pub fn send_window_metrics_event(
         &self,
         (width, height): (usize, usize),
@@ -180,7 +205,7 @@ impl FlutterEngine {
         phase: FlutterPointerPhase,
         (x, y): (f64, f64),
         signal_kind: FlutterPointerSignalKind,
-        scroll_delta_y: f64,
+        (scroll_delta_x, scroll_delta_y): (f64, f64),
         buttons: Vec<FlutterPointerMouseButton>,
     ) -> Result<(), Error> {
         let flutter_pointer_event = sys::FlutterPointerEvent {
@@ -192,7 +217,7 @@ impl FlutterEngine {
             y,
             device: 0,
             signal_kind: signal_kind.into(),
-            scroll_delta_x: 0.0,
+            scroll_delta_x,
             scroll_delta_y,
             device_kind: sys::FlutterPointerDeviceKind_kFlutterPointerDeviceKindMouse,
             buttons: buttons.into_iter().fold(0, |acc, button| {
@@ -232,6 +257,56 @@ impl FlutterEngine {
         }
     }
 
+    /// Sends a platform message and invokes `reply` with the response from the
+    /// framework.
+    ///
+    /// The reply is dropped without being invoked if the framework never
+    /// responds to the message.
+    pub fn send_platform_message_with_reply(
+        &self,
+        channel: &str,
+        message: &[u8],
+        reply: Box<dyn FnOnce(&[u8]) + Send>,
+    ) -> Result<(), Error> {
+        let reply: *mut PlatformMessageReply = Box::into_raw(Box::new(reply));
+
+        let mut response_handle = std::ptr::null_mut();
+        let result = unsafe {
+            sys::FlutterPlatformMessageCreateResponseHandle(
+                self.get_engine(),
+                Some(platform_message_reply_callback),
+                reply as *mut std::ffi::c_void,
+                &mut response_handle,
+            )
+        };
+        if result != sys::FlutterEngineResult_kSuccess {
+            drop(unsafe { Box::from_raw(reply) });
+            return Err(result.into());
+        }
+
+        let channel = CString::new(channel).unwrap();
+        let platform_message = sys::FlutterPlatformMessage {
+            struct_size: std::mem::size_of::<sys::FlutterPlatformMessage>(),
+            channel: channel.as_ptr(),
+            message: message.as_ptr(),
+            message_size: message.len(),
+            response_handle,
+        };
+        let result =
+            unsafe { sys::FlutterEngineSendPlatformMessage(self.get_engine(), &platform_message) };
+
+        // The engine holds on to the handle until the framework responds, so it
+        // is safe to release it right after sending.
+        unsafe {
+            sys::FlutterPlatformMessageReleaseResponseHandle(self.get_engine(), response_handle)
+        };
+
+        match result {
+            sys::FlutterEngineResult_kSuccess => Ok(()),
+            err => Err(err.into()),
+        }
+    }
+
     pub fn send_platform_message_response(
         &self,
         handle: crate::PlatformMessageResponseHandle,
@@ -255,45 +330,42 @@ impl FlutterEngine {
         }
     }
 
-    fn sync_ime_state(&self, client_id: i32, text: &str) -> Result<(), Error> {
-        let payload = serde_json::json!({
-            "method": "TextInputClient.updateEditingState",
-            "args": [
-                client_id,
-                {
-                    "text": text,
-                    "selectionBase": text.chars().count() as i32,
-                    "selectionExtent": text.chars().count() as i32,
-                    "composingBase": -1,
-                    "composingExtent": -1
-                }
-            ]
-        });
-        self.send_platform_message("flutter/textinput", payload.to_string().as_bytes())
-    }
-
-    pub fn send_text_input_char(&self, c: char) -> Result<(), Error> {
-        let mut state_guard = crate::text_input::IME_STATE.lock().unwrap();
-        if let Some(state) = state_guard.as_mut() {
-            state.text.push(c);
-            let id = state.client_id;
-            let text = state.text.clone();
-            drop(state_guard);
-            self.sync_ime_state(id, &text)?;
-        }
-        Ok(())
-    }
+    /// Sends a hardware key event to the framework.
+    ///
+    /// `physical` is the USB HID usage code of the key, and `logical` is the
+    /// value of the `LogicalKeyboardKey` in the framework. `character` is the
+    /// text produced by the key, which should only be set for down and repeat
+    /// events.
+    pub fn send_key_event(
+        &self,
+        event_type: FlutterKeyEventType,
+        physical: u64,
+        logical: u64,
+        character: Option<&str>,
+        synthesized: bool,
+    ) -> Result<(), Error> {
+        let character = character.and_then(|c| CString::new(c).ok());
+
+        let event = sys::FlutterKeyEvent {
+            struct_size: std::mem::size_of::<sys::FlutterKeyEvent>(),
+            timestamp: self.duration_from_start().as_micros() as f64,
+            type_: event_type.into(),
+            physical,
+            logical,
+            character: character
+                .as_ref()
+                .map_or(std::ptr::null(), |character| character.as_ptr()),
+            synthesized,
+            device_type: sys::FlutterKeyEventDeviceType_kFlutterKeyEventDeviceTypeKeyboard,
+        };
 
-    pub fn send_text_input_backspace(&self) -> Result<(), Error> {
-        let mut state_guard = crate::text_input::IME_STATE.lock().unwrap();
-        if let Some(state) = state_guard.as_mut() {
-            state.text.pop();
-            let id = state.client_id;
-            let text = state.text.clone();
-            drop(state_guard);
-            self.sync_ime_state(id, &text)?;
+        let result = unsafe {
+            sys::FlutterEngineSendKeyEvent(self.get_engine(), &event, None, std::ptr::null_mut())
+        };
+        match result {
+            sys::FlutterEngineResult_kSuccess => Ok(()),
+            err => Err(err.into()),
         }
-        Ok(())
     }
 
     pub fn schedule_frame(&self) -> Result<(), Error> {
@@ -305,6 +377,24 @@ impl FlutterEngine {
     }
 }
 
+type PlatformMessageReply = Box<dyn FnOnce(&[u8]) + Send>;
+
+extern "C" fn platform_message_reply_callback(
+    data: *const u8,
+    size: usize,
+    user_data: *mut std::os::raw::c_void,
+) {
+    let reply = unsafe { Box::from_raw(user_data as *mut PlatformMessageReply) };
+
+    let data: &[u8] = if data.is_null() {
+        &[]
+    } else {
+        unsafe { slice::from_raw_parts(data, size) }
+    };
+
+    reply(data);
+}
+
 extern "C" fn software_surface_present_callback(
     user_data: *mut std::os::raw::c_void,
     allocation: *const std::os::raw::c_void,
@@ -0,0 +1,53 @@
+//! The subset of `JSONMethodCodec` which is needed by the embedder.
+//!
+//! See https://api.flutter.dev/flutter/services/JSONMethodCodec-class.html.
+
+use serde_json::{json, Value};
+
+/// Decodes a method call, returning the method name and its arguments, which
+/// are null if there are none.
+pub fn decode_method_call(message: &[u8]) -> Option<(String, Value)> {
+    let Ok(Value::Object(mut map)) = serde_json::from_slice(message) else {
+        return None;
+    };
+    let method = map.get("method")?.as_str()?.to_string();
+    let args = map.remove("args").unwrap_or(Value::Null);
+    Some((method, args))
+}
+
+/// Encodes a successful result of a method call.
+pub fn encode_success_envelope(result: &Value) -> Vec<u8> {
+    json!([result]).to_string().into_bytes()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_method_calls() {
+        let message = br#"{"method":"TextInput.setClient","args":[1,{}]}"#;
+        assert_eq!(
+            decode_method_call(message),
+            Some(("TextInput.setClient".to_string(), json!([1, {}])))
+        );
+
+        let message = br#"{"method":"TextInput.hide"}"#;
+        assert_eq!(
+            decode_method_call(message),
+            Some(("TextInput.hide".to_string(), Value::Null))
+        );
+
+        assert_eq!(decode_method_call(br#"{"args":[]}"#), None);
+        assert_eq!(decode_method_call(b"not json"), None);
+    }
+
+    #[test]
+    fn encodes_success_envelopes() {
+        assert_eq!(encode_success_envelope(&Value::Null), b"[null]");
+        assert_eq!(
+            encode_success_envelope(&json!({ "value": true })),
+            br#"[{"value":true}]"#
+        );
+    }
+}
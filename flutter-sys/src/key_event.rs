@@ -0,0 +1,18 @@
+use crate::sys;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FlutterKeyEventType {
+    Up,
+    Down,
+    Repeat,
+}
+
+impl From<FlutterKeyEventType> for sys::FlutterKeyEventType {
+    fn from(value: FlutterKeyEventType) -> Self {
+        match value {
+            FlutterKeyEventType::Up => sys::FlutterKeyEventType_kFlutterKeyEventTypeUp,
+            FlutterKeyEventType::Down => sys::FlutterKeyEventType_kFlutterKeyEventTypeDown,
+            FlutterKeyEventType::Repeat => sys::FlutterKeyEventType_kFlutterKeyEventTypeRepeat,
+        }
+    }
+}
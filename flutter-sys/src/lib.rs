@@ -2,10 +2,13 @@ mod callbacks;
 mod engine;
 mod error;
 mod ffi;
+pub mod json_codec;
+mod key_event;
 mod pixel;
 mod pointer;
 mod project_args;
 mod semantics;
+pub mod standard_codec;
 pub mod sys;
 mod task;
 pub mod text_input;
@@ -14,6 +17,7 @@ mod user_data;
 pub use callbacks::*;
 pub use engine::*;
 pub use error::*;
+pub use key_event::*;
 pub use pixel::*;
 pub use pointer::*;
 pub use semantics::*;
@@ -1,17 +1,32 @@
 use crate::sys;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum FlutterPointerPhase {
+    Cancel,
     Up,
     Down,
+    Move,
+    Add,
+    Remove,
     Hover,
+    PanZoomStart,
+    PanZoomUpdate,
+    PanZoomEnd,
 }
 
 impl From<FlutterPointerPhase> for sys::FlutterPointerPhase {
     fn from(value: FlutterPointerPhase) -> Self {
         match value {
+            FlutterPointerPhase::Cancel => sys::FlutterPointerPhase_kCancel,
             FlutterPointerPhase::Up => sys::FlutterPointerPhase_kUp,
             FlutterPointerPhase::Down => sys::FlutterPointerPhase_kDown,
+            FlutterPointerPhase::Move => sys::FlutterPointerPhase_kMove,
+            FlutterPointerPhase::Add => sys::FlutterPointerPhase_kAdd,
+            FlutterPointerPhase::Remove => sys::FlutterPointerPhase_kRemove,
             FlutterPointerPhase::Hover => sys::FlutterPointerPhase_kHover,
+            FlutterPointerPhase::PanZoomStart => sys::FlutterPointerPhase_kPanZoomStart,
+            FlutterPointerPhase::PanZoomUpdate => sys::FlutterPointerPhase_kPanZoomUpdate,
+            FlutterPointerPhase::PanZoomEnd => sys::FlutterPointerPhase_kPanZoomEnd,
         }
     }
 }
@@ -34,6 +49,7 @@ impl From<FlutterPointerSignalKind> for sys::FlutterPointerSignalKind {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum FlutterPointerMouseButton {
     Left,
     Right,
@@ -1,6 +1,6 @@
 use crate::{
     ffi::to_string, post_platform_task_callback, runs_task_on_current_thread_callback,
-    semantics::update_semantics_callback, sys, user_data::UserData,
+    semantics::update_semantics_callback, sys, user_data::UserData, VsyncBaton,
 };
 use std::ffi::CString;
 
@@ -12,6 +12,7 @@ pub(crate) struct FlutterProjectArgs {
     #[allow(unused)]
     platform_task_runner: Box<sys::FlutterTaskRunnerDescription>,
     custom_task_runners: Box<sys::FlutterCustomTaskRunners>,
+    vsync: bool,
 }
 
 impl Drop for FlutterProjectArgs {
@@ -28,6 +29,7 @@ impl FlutterProjectArgs {
         assets_path: &str,
         icu_data_path: &str,
         user_data: *mut std::ffi::c_void,
+        vsync: bool,
     ) -> Self {
         let assets_path = CString::new(assets_path).unwrap().into_raw();
         let icu_data_path = CString::new(icu_data_path).unwrap().into_raw();
@@ -54,6 +56,7 @@ impl FlutterProjectArgs {
             icu_data_path,
             platform_task_runner,
             custom_task_runners,
+            vsync,
         }
     }
 
@@ -80,7 +83,11 @@ impl FlutterProjectArgs {
             update_semantics_custom_action_callback: None,
             persistent_cache_path: std::ptr::null(),
             is_persistent_cache_read_only: false,
-            vsync_callback: None,
+            vsync_callback: if self.vsync {
+                Some(vsync_callback)
+            } else {
+                None
+            },
             custom_dart_entrypoint: std::ptr::null(),
             custom_task_runners: &*self.custom_task_runners,
             shutdown_dart_vm_when_done: true,
@@ -92,7 +99,7 @@ impl FlutterProjectArgs {
             dart_entrypoint_argv: std::ptr::null(),
             log_message_callback: Some(log_message_callback),
             log_tag: std::ptr::null(),
-            on_pre_engine_restart_callback: None,
+            on_pre_engine_restart_callback: Some(on_pre_engine_restart_callback),
             update_semantics_callback: Some(update_semantics_callback),
             update_semantics_callback2: None,
             channel_update_callback: None,
@@ -118,6 +125,26 @@ extern "C" fn log_message_callback(
         .map(|callback| callback(tag, message));
 }
 
+extern "C" fn on_pre_engine_restart_callback(user_data: *mut ::std::os::raw::c_void) {
+    let user_data: &UserData = unsafe { &mut *(user_data as *mut UserData) };
+
+    user_data
+        .callbacks
+        .on_pre_engine_restart_callback
+        .as_ref()
+        .map(|callback| callback());
+}
+
+extern "C" fn vsync_callback(user_data: *mut ::std::os::raw::c_void, baton: isize) {
+    let user_data: &UserData = unsafe { &mut *(user_data as *mut UserData) };
+
+    user_data
+        .callbacks
+        .vsync_callback
+        .as_ref()
+        .map(|callback| callback(VsyncBaton::new(baton)));
+}
+
 extern "C" fn platform_message_callback(
     message: *const sys::FlutterPlatformMessage,
     user_data: *mut ::std::os::raw::c_void,
@@ -0,0 +1,364 @@
+//! The subset of `StandardMessageCodec` and `StandardMethodCodec` which is
+//! needed by the embedder.
+//!
+//! See https://api.flutter.dev/flutter/services/StandardMessageCodec-class.html.
+
+use std::collections::BTreeMap;
+
+const NULL: u8 = 0;
+const TRUE: u8 = 1;
+const FALSE: u8 = 2;
+const INT32: u8 = 3;
+const INT64: u8 = 4;
+const FLOAT64: u8 = 6;
+const STRING: u8 = 7;
+const UINT8_LIST: u8 = 8;
+const INT32_LIST: u8 = 9;
+const INT64_LIST: u8 = 10;
+const FLOAT64_LIST: u8 = 11;
+const LIST: u8 = 12;
+const MAP: u8 = 13;
+const FLOAT32_LIST: u8 = 14;
+
+/// A value which can be encoded by the codec.
+///
+/// Typed lists other than `Uint8List` are decoded as lists.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+    Bytes(Vec<u8>),
+    List(Vec<Value>),
+    Map(BTreeMap<String, Value>),
+}
+
+impl Value {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            Value::Int(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    /// The value of `key` if this is a map.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Map(map) => map.get(key),
+            _ => None,
+        }
+    }
+}
+
+/// Decodes a method call, returning the method name and its arguments.
+pub fn decode_method_call(message: &[u8]) -> Option<(String, Value)> {
+    let mut reader = Reader {
+        buffer: message,
+        position: 0,
+    };
+    let method = match reader.read_value()? {
+        Value::String(method) => method,
+        _ => return None,
+    };
+    let args = reader.read_value()?;
+    Some((method, args))
+}
+
+/// Encodes a successful result of a method call.
+pub fn encode_success_envelope(result: &Value) -> Vec<u8> {
+    let mut buffer = vec![0];
+    write_value(&mut buffer, result);
+    buffer
+}
+
+struct Reader<'a> {
+    buffer: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn read_bytes(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self
+            .buffer
+            .get(self.position..self.position.checked_add(len)?)?;
+        self.position += len;
+        Some(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> Option<[u8; N]> {
+        self.read_bytes(N)?.try_into().ok()
+    }
+
+    /// Skips padding so that the next read is aligned to `alignment` bytes.
+    fn align(&mut self, alignment: usize) {
+        let remainder = self.position % alignment;
+        if remainder != 0 {
+            self.position += alignment - remainder;
+        }
+    }
+
+    fn read_size(&mut self) -> Option<usize> {
+        Some(match self.read_array::<1>()?[0] {
+            254 => u16::from_le_bytes(self.read_array()?) as usize,
+            255 => u32::from_le_bytes(self.read_array()?) as usize,
+            size => size as usize,
+        })
+    }
+
+    fn read_list<const N: usize>(
+        &mut self,
+        alignment: usize,
+        f: impl Fn([u8; N]) -> Value,
+    ) -> Option<Value> {
+        let len = self.read_size()?;
+        self.align(alignment);
+        (0..len)
+            .map(|_| self.read_array().map(&f))
+            .collect::<Option<_>>()
+            .map(Value::List)
+    }
+
+    fn read_value(&mut self) -> Option<Value> {
+        Some(match self.read_array::<1>()?[0] {
+            NULL => Value::Null,
+            TRUE => Value::Bool(true),
+            FALSE => Value::Bool(false),
+            INT32 => Value::Int(i32::from_le_bytes(self.read_array()?) as i64),
+            INT64 => Value::Int(i64::from_le_bytes(self.read_array()?)),
+            FLOAT64 => {
+                self.align(8);
+                Value::Float(f64::from_le_bytes(self.read_array()?))
+            }
+            STRING => {
+                let len = self.read_size()?;
+                Value::String(String::from_utf8(self.read_bytes(len)?.to_vec()).ok()?)
+            }
+            UINT8_LIST => {
+                let len = self.read_size()?;
+                Value::Bytes(self.read_bytes(len)?.to_vec())
+            }
+            INT32_LIST => {
+                self.read_list(4, |bytes| Value::Int(i32::from_le_bytes(bytes) as i64))?
+            }
+            INT64_LIST => self.read_list(8, |bytes| Value::Int(i64::from_le_bytes(bytes)))?,
+            FLOAT32_LIST => {
+                self.read_list(4, |bytes| Value::Float(f32::from_le_bytes(bytes) as f64))?
+            }
+            FLOAT64_LIST => self.read_list(8, |bytes| Value::Float(f64::from_le_bytes(bytes)))?,
+            LIST => {
+                let len = self.read_size()?;
+                Value::List((0..len).map(|_| self.read_value()).collect::<Option<_>>()?)
+            }
+            MAP => {
+                let len = self.read_size()?;
+                let mut map = BTreeMap::new();
+                for _ in 0..len {
+                    // Only string keys are supported.
+                    let Value::String(key) = self.read_value()? else {
+                        return None;
+                    };
+                    map.insert(key, self.read_value()?);
+                }
+                Value::Map(map)
+            }
+            _ => return None,
+        })
+    }
+}
+
+fn write_size(buffer: &mut Vec<u8>, size: usize) {
+    if size < 254 {
+        buffer.push(size as u8);
+    } else if size <= u16::MAX as usize {
+        buffer.push(254);
+        buffer.extend_from_slice(&(size as u16).to_le_bytes());
+    } else {
+        buffer.push(255);
+        buffer.extend_from_slice(&(size as u32).to_le_bytes());
+    }
+}
+
+fn write_value(buffer: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Null => buffer.push(NULL),
+        Value::Bool(true) => buffer.push(TRUE),
+        Value::Bool(false) => buffer.push(FALSE),
+        Value::Int(value) => match i32::try_from(*value) {
+            Ok(value) => {
+                buffer.push(INT32);
+                buffer.extend_from_slice(&value.to_le_bytes());
+            }
+            Err(_) => {
+                buffer.push(INT64);
+                buffer.extend_from_slice(&value.to_le_bytes());
+            }
+        },
+        Value::Float(value) => {
+            buffer.push(FLOAT64);
+            while buffer.len() % 8 != 0 {
+                buffer.push(0);
+            }
+            buffer.extend_from_slice(&value.to_le_bytes());
+        }
+        Value::String(value) => {
+            buffer.push(STRING);
+            write_size(buffer, value.len());
+            buffer.extend_from_slice(value.as_bytes());
+        }
+        Value::Bytes(value) => {
+            buffer.push(UINT8_LIST);
+            write_size(buffer, value.len());
+            buffer.extend_from_slice(value);
+        }
+        Value::List(values) => {
+            buffer.push(LIST);
+            write_size(buffer, values.len());
+            for value in values {
+                write_value(buffer, value);
+            }
+        }
+        Value::Map(map) => {
+            buffer.push(MAP);
+            write_size(buffer, map.len());
+            for (key, value) in map {
+                write_value(buffer, &Value::String(key.clone()));
+                write_value(buffer, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(value: &Value) -> Vec<u8> {
+        let mut buffer = vec![];
+        write_value(&mut buffer, value);
+        buffer
+    }
+
+    /// Decodes `buffer`, checking that all of it is read.
+    fn decode(buffer: &[u8]) -> Option<Value> {
+        let mut reader = Reader {
+            buffer,
+            position: 0,
+        };
+        let value = reader.read_value()?;
+        assert_eq!(reader.position, buffer.len());
+        Some(value)
+    }
+
+    fn assert_round_trips(value: Value) {
+        assert_eq!(decode(&encode(&value)), Some(value));
+    }
+
+    #[test]
+    fn round_trips_values() {
+        assert_round_trips(Value::Null);
+        assert_round_trips(Value::Bool(true));
+        assert_round_trips(Value::Bool(false));
+        assert_round_trips(Value::String("héllo".to_string()));
+        assert_round_trips(Value::Bytes(vec![1, 2, 3]));
+        assert_round_trips(Value::List(vec![Value::Int(1), Value::Null]));
+        assert_round_trips(Value::Map(BTreeMap::from([
+            ("a".to_string(), Value::Int(1)),
+            ("b".to_string(), Value::List(vec![])),
+        ])));
+    }
+
+    #[test]
+    fn narrows_ints() {
+        for value in [0, -1, i32::MIN as i64, i32::MAX as i64] {
+            assert_eq!(encode(&Value::Int(value))[0], INT32);
+            assert_round_trips(Value::Int(value));
+        }
+        for value in [i32::MAX as i64 + 1, i32::MIN as i64 - 1, i64::MAX] {
+            assert_eq!(encode(&Value::Int(value))[0], INT64);
+            assert_round_trips(Value::Int(value));
+        }
+    }
+
+    #[test]
+    fn encodes_sizes() {
+        for (len, header) in [
+            (253, vec![253]),
+            (254, vec![254, 254, 0]),
+            (65536, vec![255, 0, 0, 1, 0]),
+        ] {
+            let value = Value::String("a".repeat(len));
+            let buffer = encode(&value);
+            assert_eq!(buffer[0], STRING);
+            assert_eq!(buffer[1..1 + header.len()], header);
+            assert_eq!(buffer.len(), 1 + header.len() + len);
+            assert_round_trips(value);
+        }
+    }
+
+    #[test]
+    fn aligns_floats() {
+        // The float starts after 3 bytes, so it is padded to 8.
+        let value = Value::List(vec![Value::Bool(true), Value::Float(1.5)]);
+        let buffer = encode(&value);
+        assert_eq!(buffer[..8], [LIST, 2, TRUE, FLOAT64, 0, 0, 0, 0]);
+        assert_eq!(buffer[8..], 1.5f64.to_le_bytes());
+        assert_round_trips(value);
+
+        let value = Value::List(vec![
+            Value::String("ab".to_string()),
+            Value::Float(-0.25),
+            Value::Float(2.0),
+        ]);
+        assert_eq!(encode(&value).len(), 32);
+        assert_round_trips(value);
+    }
+
+    #[test]
+    fn decodes_typed_lists() {
+        let mut buffer = vec![INT32_LIST, 2, 0, 0];
+        buffer.extend_from_slice(&1i32.to_le_bytes());
+        buffer.extend_from_slice(&(-2i32).to_le_bytes());
+        assert_eq!(
+            decode(&buffer),
+            Some(Value::List(vec![Value::Int(1), Value::Int(-2)]))
+        );
+
+        let mut buffer = vec![FLOAT64_LIST, 1, 0, 0, 0, 0, 0, 0];
+        buffer.extend_from_slice(&2.5f64.to_le_bytes());
+        assert_eq!(decode(&buffer), Some(Value::List(vec![Value::Float(2.5)])));
+
+        // Truncated.
+        assert_eq!(decode(&buffer[..12]), None);
+    }
+
+    #[test]
+    fn decodes_activate_system_cursor() {
+        let mut message = vec![STRING, 20];
+        message.extend_from_slice(b"activateSystemCursor");
+        message.extend_from_slice(&[MAP, 2, STRING, 6]);
+        message.extend_from_slice(b"device");
+        message.extend_from_slice(&[INT32, 1, 0, 0, 0, STRING, 4]);
+        message.extend_from_slice(b"kind");
+        message.extend_from_slice(&[STRING, 5]);
+        message.extend_from_slice(b"click");
+
+        let (method, args) = decode_method_call(&message).unwrap();
+        assert_eq!(method, "activateSystemCursor");
+        assert_eq!(args.get("device").and_then(Value::as_i64), Some(1));
+        assert_eq!(args.get("kind").and_then(Value::as_str), Some("click"));
+    }
+
+    #[test]
+    fn encodes_success_envelopes() {
+        assert_eq!(encode_success_envelope(&Value::Null), [0, NULL]);
+    }
+}
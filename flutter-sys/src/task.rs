@@ -19,9 +19,19 @@ impl EngineTask {
     }
 
     pub fn can_run_now(&self) -> bool {
-        let current_time_nanos = unsafe { sys::FlutterEngineGetCurrentTime() };
-        self.target_time_nanos < current_time_nanos
+        self.target_time_nanos < current_time_nanos()
     }
+
+    /// The time at which the task should run, on the clock of
+    /// [current_time_nanos].
+    pub fn target_time_nanos(&self) -> u64 {
+        self.target_time_nanos
+    }
+}
+
+/// The current time on the clock which the engine uses for tasks.
+pub fn current_time_nanos() -> u64 {
+    unsafe { sys::FlutterEngineGetCurrentTime() }
 }
 
 impl EngineTask {
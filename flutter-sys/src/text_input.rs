@@ -1,49 +1,603 @@
-use once_cell::sync::Lazy;
-use serde_json::Value;
-use std::sync::Mutex;
+use crate::{json_codec, Error, FlutterEngine, PlatformMessage};
+use serde_json::{json, Map, Value};
 
-pub struct ImeState {
+pub const CHANNEL: &str = "flutter/textinput";
+
+/// Implements the platform side of the `flutter/textinput` channel, which
+/// edits the text of the focused text field.
+///
+/// See https://api.flutter.dev/flutter/services/SystemChannels/textInput-constant.html.
+#[derive(Default)]
+pub struct TextInputPlugin {
+    /// The client which receives input, from `TextInput.setClient` until
+    /// `TextInput.clearClient`.
+    client: Option<TextInputClient>,
+}
+
+/// A text field in the framework which is connected to the plugin.
+pub struct TextInputClient {
     pub client_id: i32,
-    pub text: String,
+    /// The `TextInputAction` to perform when enter is pressed, e.g.
+    /// `TextInputAction.done`.
+    pub input_action: String,
+    pub multiline: bool,
+    pub editing_state: TextEditingState,
 }
 
-pub static IME_STATE: Lazy<Mutex<Option<ImeState>>> = Lazy::new(|| Mutex::new(None));
-
-pub fn handle_message(message: &crate::PlatformMessage) -> bool {
-    let channel_str = &message.channel;
-
-    // See https://api.flutter.dev/flutter/services/SystemChannels/textInput-constant.html.
-
-    if channel_str == "flutter/textinput" {
-        let slice = &message.message;
-        if let Ok(Value::Object(map)) = serde_json::from_slice(slice) {
-            if let Some(Value::String(method)) = map.get("method") {
-                match method.as_str() {
-                    "TextInput.setClient" => {
-                        if let Some(Value::Array(args)) = map.get("args") {
-                            if let Some(id) = args.get(0).and_then(|v| v.as_i64()) {
-                                *IME_STATE.lock().unwrap() = Some(ImeState {
-                                    client_id: id as i32,
-                                    text: String::new(),
-                                });
-                            }
-                        }
-                    }
-                    "TextInput.setEditingState" => {
-                        if let Some(Value::Array(args)) = map.get("args") {
-                            if let Some(Value::Object(state_map)) = args.get(0) {
-                                if let Some(text) = state_map.get("text").and_then(|v| v.as_str()) {
-                                    if let Some(state) = IME_STATE.lock().unwrap().as_mut() {
-                                        state.text = text.to_string();
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    _ => {}
+impl TextInputPlugin {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Disconnects the client, e.g. when the engine restarts.
+    pub fn reset(&mut self) {
+        self.client = None;
+    }
+
+    /// Updates the client from a method call of the framework, returning the
+    /// response, or `None` if the method isn't implemented.
+    pub fn handle_message(&mut self, message: &PlatformMessage) -> Option<Vec<u8>> {
+        let (method, args) = json_codec::decode_method_call(&message.message)?;
+
+        match method.as_str() {
+            "TextInput.setClient" => {
+                let args = args.as_array()?;
+                let id = args.first().and_then(Value::as_i64)?;
+                let config = args.get(1).and_then(Value::as_object);
+
+                let input_action = config
+                    .and_then(|config| config.get("inputAction"))
+                    .and_then(Value::as_str)
+                    .unwrap_or("TextInputAction.done");
+                let multiline = config
+                    .and_then(|config| config.get("inputType"))
+                    .and_then(|input_type| input_type.get("name"))
+                    .and_then(Value::as_str)
+                    == Some("TextInputType.multiline");
+
+                self.client = Some(TextInputClient {
+                    client_id: id as i32,
+                    input_action: input_action.to_string(),
+                    multiline,
+                    editing_state: TextEditingState::default(),
+                });
+            }
+            "TextInput.setEditingState" => {
+                let editing_state = args.as_object().and_then(TextEditingState::from_json)?;
+                if let Some(client) = self.client.as_mut() {
+                    client.editing_state = editing_state;
                 }
             }
+            "TextInput.clearClient" => {
+                self.client = None;
+            }
+            // Terminals always have a keyboard, so input goes to the client
+            // whether or not it is shown.
+            "TextInput.show" | "TextInput.hide" => {}
+            _ => return None,
+        }
+
+        Some(json_codec::encode_success_envelope(&Value::Null))
+    }
+
+    /// Inserts `text` in place of the selection of the attached client.
+    pub fn insert_text(&mut self, engine: &FlutterEngine, text: &str) -> Result<(), Error> {
+        if let Some(client) = self.client.as_mut() {
+            client.editing_state.insert(text);
+            let (id, editing_state) = (client.client_id, client.editing_state.to_json());
+            update_editing_state(engine, id, editing_state)?;
+        }
+        Ok(())
+    }
+
+    /// Inserts pasted `text` in place of the selection of the attached client,
+    /// as a single update.
+    ///
+    /// Newlines are removed for clients which only accept a single line.
+    pub fn paste(&mut self, engine: &FlutterEngine, text: &str) -> Result<(), Error> {
+        let Some(client) = self.client.as_mut() else {
+            return Ok(());
+        };
+
+        // Terminals usually send carriage returns for newlines.
+        let text = if client.multiline {
+            text.replace("\r\n", "\n").replace('\r', "\n")
+        } else {
+            text.replace(['\r', '\n'], "")
+        };
+        self.insert_text(engine, &text)
+    }
+
+    /// Applies `key` to the attached client.
+    pub fn handle_key(
+        &mut self,
+        engine: &FlutterEngine,
+        key: TextInputKey,
+        extend_selection: bool,
+    ) -> Result<(), Error> {
+        if let Some(client) = self.client.as_mut() {
+            let action = client.handle_key(key, extend_selection);
+            let (id, editing_state) = (client.client_id, client.editing_state.to_json());
+            update_editing_state(engine, id, editing_state)?;
+            if let Some(action) = action {
+                perform_action(engine, id, &action)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn update_editing_state(
+    engine: &FlutterEngine,
+    client_id: i32,
+    editing_state: Value,
+) -> Result<(), Error> {
+    let payload = json!({
+        "method": "TextInputClient.updateEditingState",
+        "args": [client_id, editing_state]
+    });
+    engine.send_platform_message(CHANNEL, payload.to_string().as_bytes())
+}
+
+fn perform_action(engine: &FlutterEngine, client_id: i32, action: &str) -> Result<(), Error> {
+    let payload = json!({
+        "method": "TextInputClient.performAction",
+        "args": [client_id, action]
+    });
+    engine.send_platform_message(CHANNEL, payload.to_string().as_bytes())
+}
+
+/// Keys which edit the text of the client, other than those which insert
+/// characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextInputKey {
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Home,
+    End,
+    Enter,
+}
+
+impl TextInputClient {
+    /// Applies `key` to the editing state, returning the `TextInputAction`
+    /// which should be performed, if any.
+    pub fn handle_key(&mut self, key: TextInputKey, extend_selection: bool) -> Option<String> {
+        let state = &mut self.editing_state;
+        match key {
+            TextInputKey::Backspace => state.backspace(),
+            TextInputKey::Delete => state.delete_forward(),
+            TextInputKey::Left => state.move_left(extend_selection),
+            TextInputKey::Right => state.move_right(extend_selection),
+            TextInputKey::Home => state.move_to_line_start(extend_selection),
+            TextInputKey::End => state.move_to_line_end(extend_selection),
+            TextInputKey::Enter => {
+                if self.multiline {
+                    state.insert("\n");
+                }
+                return Some(self.input_action.clone());
+            }
+        }
+        None
+    }
+}
+
+/// The equivalent of `TextEditingValue` in the framework.
+///
+/// Like the framework, offsets are in UTF-16 code units.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TextEditingState {
+    pub text: String,
+    pub selection_base: usize,
+    pub selection_extent: usize,
+    pub composing: Option<(usize, usize)>,
+}
+
+impl TextEditingState {
+    fn from_json(map: &Map<String, Value>) -> Option<Self> {
+        let text = map.get("text")?.as_str()?.to_string();
+        let len = utf16_len(&text);
+
+        // Offsets are -1 when there is no selection or composing region.
+        let offset = |key: &str| {
+            map.get(key)
+                .and_then(Value::as_i64)
+                .filter(|offset| *offset >= 0)
+                .map(|offset| (offset as usize).min(len))
+        };
+
+        let composing = match (offset("composingBase"), offset("composingExtent")) {
+            (Some(base), Some(extent)) if base != extent => Some((base, extent)),
+            _ => None,
+        };
+
+        Some(Self {
+            selection_base: offset("selectionBase").unwrap_or(len),
+            selection_extent: offset("selectionExtent").unwrap_or(len),
+            composing,
+            text,
+        })
+    }
+
+    pub fn to_json(&self) -> Value {
+        let (composing_base, composing_extent) = self
+            .composing
+            .map_or((-1, -1), |(base, extent)| (base as i64, extent as i64));
+
+        json!({
+            "text": self.text,
+            "selectionBase": self.selection_base,
+            "selectionExtent": self.selection_extent,
+            "selectionAffinity": "TextAffinity.downstream",
+            "selectionIsDirectional": false,
+            "composingBase": composing_base,
+            "composingExtent": composing_extent,
+        })
+    }
+
+    /// The start and end of the selection.
+    fn selection(&self) -> (usize, usize) {
+        (
+            self.selection_base.min(self.selection_extent),
+            self.selection_base.max(self.selection_extent),
+        )
+    }
+
+    /// Replaces the text between `start` and `end` with `text`, placing the
+    /// caret after it.
+    fn replace(&mut self, start: usize, end: usize, text: &str) {
+        let range = byte_index(&self.text, start)..byte_index(&self.text, end);
+        self.text.replace_range(range, text);
+
+        let caret = start + utf16_len(text);
+        self.selection_base = caret;
+        self.selection_extent = caret;
+        self.composing = None;
+    }
+
+    fn select(&mut self, extent: usize, extend_selection: bool) {
+        self.selection_extent = extent;
+        if !extend_selection {
+            self.selection_base = extent;
+        }
+        self.composing = None;
+    }
+
+    /// Inserts `text` in place of the selection.
+    pub fn insert(&mut self, text: &str) {
+        let (start, end) = self.selection();
+        self.replace(start, end, text);
+    }
+
+    pub fn backspace(&mut self) {
+        let (start, end) = self.selection();
+        if start != end {
+            self.replace(start, end, "");
+        } else {
+            self.replace(previous_boundary(&self.text, start), end, "");
+        }
+    }
+
+    pub fn delete_forward(&mut self) {
+        let (start, end) = self.selection();
+        if start != end {
+            self.replace(start, end, "");
+        } else {
+            self.replace(start, next_boundary(&self.text, end), "");
+        }
+    }
+
+    pub fn move_left(&mut self, extend_selection: bool) {
+        let (start, end) = self.selection();
+        if start != end && !extend_selection {
+            // Collapse the selection instead of moving the caret.
+            self.select(start, false);
+        } else {
+            self.select(
+                previous_boundary(&self.text, self.selection_extent),
+                extend_selection,
+            );
+        }
+    }
+
+    pub fn move_right(&mut self, extend_selection: bool) {
+        let (start, end) = self.selection();
+        if start != end && !extend_selection {
+            // Collapse the selection instead of moving the caret.
+            self.select(end, false);
+        } else {
+            self.select(
+                next_boundary(&self.text, self.selection_extent),
+                extend_selection,
+            );
+        }
+    }
+
+    pub fn move_to_line_start(&mut self, extend_selection: bool) {
+        let caret = byte_index(&self.text, self.selection_extent);
+        let line_start = self.text[..caret].rfind('\n').map_or(0, |index| index + 1);
+        self.select(utf16_len(&self.text[..line_start]), extend_selection);
+    }
+
+    pub fn move_to_line_end(&mut self, extend_selection: bool) {
+        let caret = byte_index(&self.text, self.selection_extent);
+        let line_end = self.text[caret..]
+            .find('\n')
+            .map_or(self.text.len(), |index| caret + index);
+        self.select(utf16_len(&self.text[..line_end]), extend_selection);
+    }
+}
+
+fn utf16_len(text: &str) -> usize {
+    text.encode_utf16().count()
+}
+
+/// Converts an UTF-16 offset into an index of `text`.
+fn byte_index(text: &str, offset: usize) -> usize {
+    let mut utf16_offset = 0;
+    for (index, c) in text.char_indices() {
+        if utf16_offset >= offset {
+            return index;
+        }
+        utf16_offset += c.len_utf16();
+    }
+    text.len()
+}
+
+const ZERO_WIDTH_JOINER: char = '\u{200D}';
+
+/// Whether `c` is drawn as part of the character before it, e.g. a combining
+/// accent or a skin tone, so that the caret never ends up between them.
+///
+/// This is a subset of the characters which extend grapheme clusters, see
+/// https://unicode.org/reports/tr29/#Grapheme_Cluster_Boundaries.
+fn is_extending(c: char) -> bool {
+    matches!(
+        c,
+        '\u{0300}'..='\u{036F}'
+            | '\u{1AB0}'..='\u{1AFF}'
+            | '\u{1DC0}'..='\u{1DFF}'
+            | '\u{20D0}'..='\u{20FF}'
+            | '\u{FE00}'..='\u{FE0F}'
+            | '\u{FE20}'..='\u{FE2F}'
+            | '\u{1F3FB}'..='\u{1F3FF}'
+            | '\u{E0020}'..='\u{E007F}'
+            | ZERO_WIDTH_JOINER
+    )
+}
+
+/// The UTF-16 offset of the character before `offset`, including the
+/// characters which extend it.
+fn previous_boundary(text: &str, offset: usize) -> usize {
+    let mut boundary = offset;
+    let mut chars = text[..byte_index(text, offset)].chars().rev().peekable();
+    while let Some(c) = chars.next() {
+        boundary -= c.len_utf16();
+        // Characters joined by a zero width joiner are drawn as one, e.g. in
+        // emoji sequences.
+        if !is_extending(c) && chars.peek() != Some(&ZERO_WIDTH_JOINER) {
+            break;
         }
     }
-    false
+    boundary
+}
+
+/// The UTF-16 offset of the character after `offset`, including the
+/// characters which extend it.
+fn next_boundary(text: &str, offset: usize) -> usize {
+    let mut boundary = offset;
+    let mut previous = None;
+    for c in text[byte_index(text, offset)..].chars() {
+        if previous.is_some_and(|previous| !is_extending(c) && previous != ZERO_WIDTH_JOINER) {
+            break;
+        }
+        boundary += c.len_utf16();
+        previous = Some(c);
+    }
+    boundary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::PlatformMessageResponseHandle;
+
+    /// The state with the caret or selection marked by `|` in `text`, e.g.
+    /// `"a|bc|"` for a selection of "bc".
+    fn state(text: &str) -> TextEditingState {
+        let markers: Vec<usize> = text
+            .match_indices('|')
+            .enumerate()
+            .map(|(i, (index, _))| utf16_len(&text[..index]) - i)
+            .collect();
+        let (selection_base, selection_extent) = match markers[..] {
+            [caret] => (caret, caret),
+            [base, extent] => (base, extent),
+            _ => panic!("expected one or two markers in {text:?}"),
+        };
+        TextEditingState {
+            text: text.replace('|', ""),
+            selection_base,
+            selection_extent,
+            composing: None,
+        }
+    }
+
+    #[test]
+    fn converts_utf16_offsets_to_byte_indices() {
+        let text = "aé😀b";
+        assert_eq!(utf16_len(text), 5);
+        assert_eq!(byte_index(text, 0), 0);
+        assert_eq!(byte_index(text, 1), 1);
+        assert_eq!(byte_index(text, 2), 3);
+        // After the surrogate pair of the emoji.
+        assert_eq!(byte_index(text, 4), 7);
+        assert_eq!(byte_index(text, 5), 8);
+        assert_eq!(byte_index(text, 10), 8);
+    }
+
+    #[test]
+    fn moves_over_surrogate_pairs() {
+        let text = "a😀b";
+        assert_eq!(next_boundary(text, 1), 3);
+        assert_eq!(previous_boundary(text, 3), 1);
+    }
+
+    #[test]
+    fn moves_over_combining_characters() {
+        let text = "ae\u{301}b";
+        assert_eq!(next_boundary(text, 1), 3);
+        assert_eq!(previous_boundary(text, 3), 1);
+    }
+
+    #[test]
+    fn moves_over_emoji_sequences() {
+        // Woman, zero width joiner, laptop, then a thumbs up with a skin tone.
+        let text = "👩\u{200D}💻👍\u{1F3FD}";
+        assert_eq!(next_boundary(text, 0), 5);
+        assert_eq!(next_boundary(text, 5), 9);
+        assert_eq!(previous_boundary(text, 9), 5);
+        assert_eq!(previous_boundary(text, 5), 0);
+    }
+
+    #[test]
+    fn stops_at_the_ends_of_the_text() {
+        assert_eq!(previous_boundary("ab", 0), 0);
+        assert_eq!(next_boundary("ab", 2), 2);
+        assert_eq!(next_boundary("", 0), 0);
+    }
+
+    #[test]
+    fn inserts_in_place_of_the_selection() {
+        let mut editing_state = state("a|bc|d");
+        editing_state.insert("😀");
+        assert_eq!(editing_state, state("a😀|d"));
+    }
+
+    #[test]
+    fn deletes_characters() {
+        let mut editing_state = state("ae\u{301}|b");
+        editing_state.backspace();
+        assert_eq!(editing_state, state("a|b"));
+
+        let mut editing_state = state("a|😀b");
+        editing_state.delete_forward();
+        assert_eq!(editing_state, state("a|b"));
+
+        let mut editing_state = state("|a");
+        editing_state.backspace();
+        assert_eq!(editing_state, state("|a"));
+    }
+
+    #[test]
+    fn deletes_the_selection() {
+        let mut editing_state = state("a|bc|d");
+        editing_state.backspace();
+        assert_eq!(editing_state, state("a|d"));
+
+        let mut editing_state = state("a|bc|d");
+        editing_state.delete_forward();
+        assert_eq!(editing_state, state("a|d"));
+    }
+
+    #[test]
+    fn extends_the_selection() {
+        let mut editing_state = state("a|bc");
+        editing_state.move_right(true);
+        editing_state.move_right(true);
+        assert_eq!(editing_state, state("a|bc|"));
+
+        editing_state.move_left(true);
+        assert_eq!(editing_state, state("a|b|c"));
+    }
+
+    #[test]
+    fn collapses_the_selection() {
+        let mut editing_state = state("a|bc|d");
+        editing_state.move_left(false);
+        assert_eq!(editing_state, state("a|bcd"));
+
+        let mut editing_state = state("a|bc|d");
+        editing_state.move_right(false);
+        assert_eq!(editing_state, state("abc|d"));
+    }
+
+    #[test]
+    fn moves_to_the_line_start_and_end() {
+        let mut editing_state = state("ab\n😀c|d\nef");
+        editing_state.move_to_line_start(false);
+        assert_eq!(editing_state, state("ab\n|😀cd\nef"));
+
+        editing_state.move_to_line_end(true);
+        assert_eq!(editing_state, state("ab\n|😀cd|\nef"));
+
+        let mut editing_state = state("a|b");
+        editing_state.move_to_line_end(false);
+        assert_eq!(editing_state, state("ab|"));
+        editing_state.move_to_line_start(false);
+        assert_eq!(editing_state, state("|ab"));
+    }
+
+    #[test]
+    fn clamps_offsets_from_json() {
+        let json = json!({
+            "text": "a😀",
+            "selectionBase": -1,
+            "selectionExtent": 10,
+            "composingBase": 1,
+            "composingExtent": 3,
+        });
+        let editing_state = TextEditingState::from_json(json.as_object().unwrap()).unwrap();
+        assert_eq!(editing_state.selection_base, 3);
+        assert_eq!(editing_state.selection_extent, 3);
+        assert_eq!(editing_state.composing, Some((1, 3)));
+    }
+
+    fn message(method: &str, args: Value) -> PlatformMessage {
+        PlatformMessage {
+            channel: CHANNEL.to_string(),
+            message: json!({ "method": method, "args": args })
+                .to_string()
+                .into_bytes(),
+            response_handle: PlatformMessageResponseHandle::new(std::ptr::null()),
+        }
+    }
+
+    #[test]
+    fn sends_input_to_the_client_until_it_is_cleared() {
+        let mut plugin = TextInputPlugin::new();
+        assert!(plugin.client.as_ref().is_none());
+
+        let set_client = message("TextInput.setClient", json!([3, {}]));
+        assert!(plugin.handle_message(&set_client).is_some());
+        // Without `TextInput.show`, which is only sent for some text fields.
+        assert_eq!(
+            plugin.client.as_ref().map(|client| client.client_id),
+            Some(3)
+        );
+
+        plugin.handle_message(&message("TextInput.hide", Value::Null));
+        assert!(plugin.client.as_ref().is_some());
+
+        plugin.handle_message(&message("TextInput.clearClient", Value::Null));
+        assert!(plugin.client.as_ref().is_none());
+    }
+
+    #[test]
+    fn performs_the_input_action_on_enter() {
+        let mut client = TextInputClient {
+            client_id: 1,
+            input_action: "TextInputAction.newline".to_string(),
+            multiline: true,
+            editing_state: state("a|"),
+        };
+        let action = client.handle_key(TextInputKey::Enter, false);
+        assert_eq!(action.as_deref(), Some("TextInputAction.newline"));
+        assert_eq!(client.editing_state, state("a\n|"));
+
+        client.multiline = false;
+        client.handle_key(TextInputKey::Enter, false);
+        assert_eq!(client.editing_state, state("a\n|"));
+    }
 }